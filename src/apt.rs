@@ -1,9 +1,32 @@
-use anyhow::{Result, anyhow};
-use async_compression::futures::bufread::GzipDecoder;
-use deb_control_codec::{asynchronous_codec::FramedRead, prelude::*};
-use futures_util::{StreamExt, TryStreamExt};
+use anyhow::{Context, Result, anyhow};
+use async_compression::futures::bufread::{BzDecoder, GzipDecoder};
+use deb_control_codec::{
+    asynchronous_codec::{BytesMut, Decoder, FramedRead},
+    prelude::*,
+};
+use futures_util::{AsyncBufRead, AsyncRead, StreamExt, TryStreamExt, io::BufReader};
+use reqwest::{
+    Client, ClientBuilder, Method, StatusCode,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::{CONTENT_ENCODING, HeaderMap, HeaderName, HeaderValue},
+    redirect, tls,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{self, AtomicBool, AtomicU64},
+    },
+    time::{Duration, Instant},
+};
+use tracing::Instrument;
 use url::Url;
 
+use crate::config::{Config, RepoKind, TlsVersion};
+
 fn parse_array(entry: Entry, value: &mut Option<Vec<String>>) -> Result<()> {
     if value.is_some() {
         return Err(anyhow!("entry {} already set", entry.key));
@@ -20,11 +43,119 @@ fn parse_string(entry: Entry, value: &mut Option<String>) -> Result<()> {
     Ok(())
 }
 
+// Parses the names in a relationship field like Provides, dropping versions and arch qualifiers,
+// so "foo (= 1.0), bar:any" becomes foo and bar
+fn parse_names(entry: Entry, value: &mut Option<Vec<String>>) -> Result<()> {
+    if value.is_some() {
+        return Err(anyhow!("entry {} already set", entry.key));
+    }
+    let mut names = Vec::new();
+    for relation in entry.value.split(',') {
+        let name = relation.split(['(', ' ', ':']).find(|x| !x.is_empty());
+        if let Some(name) = name {
+            names.push(name.trim().to_string());
+        }
+    }
+    *value = Some(names);
+    Ok(())
+}
+
+fn parse_section(entry: Entry, value: &mut Option<String>) -> Result<()> {
+    parse_string(entry, value)?;
+    // Sections outside of main may be prefixed with the component, like universe/libs
+    if let Some(section) = value
+        && let Some((_component, name)) = section.split_once('/')
+    {
+        *section = name.to_string();
+    }
+    Ok(())
+}
+
+// Sizes in bytes of the indices listed in a Release, by path relative to the suite
+pub type IndexSizes = BTreeMap<String, u64>;
+
+// Parses the paths and sizes of the indices listed in a checksum field of a Release
+fn parse_files(entry: Entry, value: &mut IndexSizes) -> Result<()> {
+    for line in entry.value.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_sha256, size, path] = fields[..] else {
+            return Err(anyhow!("{}: invalid line {line:?}", entry.key));
+        };
+        let size = size
+            .parse()
+            .with_context(|| format!("{}: invalid size in {line:?}", entry.key))?;
+        value.insert(path.to_string(), size);
+    }
+    Ok(())
+}
+
 #[derive(Debug, Default)]
 pub struct Release {
     pub archs: Option<Vec<String>>,
     pub codename: Option<String>,
     pub components: Option<Vec<String>>,
+    pub date: Option<String>,
+    pub valid_until: Option<String>,
+    pub files: IndexSizes,
+}
+
+// Parses a Release date as a Unix timestamp. Release files give the zone as UTC, which RFC 2822
+// spells +0000.
+fn parse_release_date(date: &str) -> Option<i64> {
+    let date = match date.strip_suffix(" UTC") {
+        Some(date) => format!("{date} +0000"),
+        None => date.to_string(),
+    };
+    match chrono::DateTime::parse_from_rfc2822(&date) {
+        Ok(date) => Some(date.timestamp()),
+        Err(err) => {
+            log::warn!("invalid Release date {date:?}: {err}");
+            None
+        }
+    }
+}
+
+impl Release {
+    // Date as a Unix timestamp
+    pub fn timestamp(&self) -> Option<i64> {
+        parse_release_date(self.date.as_deref()?)
+    }
+
+    // Whether the Release is past its Valid-Until, after which apt refuses to use the suite
+    pub fn expired(&self) -> bool {
+        self.valid_until
+            .as_deref()
+            .and_then(parse_release_date)
+            .is_some_and(|valid_until| valid_until < chrono::Utc::now().timestamp())
+    }
+
+    // Sources indices of a component when the Release splits them into several files, like
+    // main/source/Sources-1.gz and main/source/Sources-2.gz, as paths without the compression
+    // extension. Empty when the component has a single Sources index.
+    pub fn split_sources(&self, component: &str) -> Vec<String> {
+        let prefix = format!("{component}/source/Sources-");
+        let mut indices = Vec::new();
+        for file in self.files.keys() {
+            let Some(part) = file.strip_prefix(&prefix) else {
+                continue;
+            };
+            // Listed once per compression, of which only the base path is kept
+            let part = part
+                .strip_suffix(".gz")
+                .or_else(|| part.strip_suffix(".bz2"))
+                .or_else(|| part.strip_suffix(".xz"))
+                .unwrap_or(part);
+            let index = format!("{prefix}{part}");
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+        indices
+    }
 }
 
 impl TryFrom<Control<'_>> for Release {
@@ -36,6 +167,9 @@ impl TryFrom<Control<'_>> for Release {
                 "Architectures" => parse_array(entry, &mut this.archs)?,
                 "Codename" => parse_string(entry, &mut this.codename)?,
                 "Components" => parse_array(entry, &mut this.components)?,
+                "Date" => parse_string(entry, &mut this.date)?,
+                "Valid-Until" => parse_string(entry, &mut this.valid_until)?,
+                "SHA256" => parse_files(entry, &mut this.files)?,
                 _ => {}
             }
         }
@@ -46,9 +180,15 @@ impl TryFrom<Control<'_>> for Release {
 #[derive(Debug, Default)]
 pub struct Package {
     pub package: Option<String>,
+    pub arch: Option<String>,
     pub archs: Option<Vec<String>>,
     pub version: Option<String>,
     pub source: Option<String>,
+    pub section: Option<String>,
+    // Virtual packages this satisfies
+    pub provides: Option<Vec<String>>,
+    // Path of the .deb in the pool, relative to the repo
+    pub filename: Option<String>,
 }
 
 impl TryFrom<Control<'_>> for Package {
@@ -58,9 +198,13 @@ impl TryFrom<Control<'_>> for Package {
         for entry in control {
             match entry.key {
                 "Package" => parse_string(entry, &mut this.package)?,
+                "Architecture" => parse_string(entry, &mut this.arch)?,
                 "Architectures" => parse_array(entry, &mut this.archs)?,
                 "Version" => parse_string(entry, &mut this.version)?,
                 "Source" => parse_string(entry, &mut this.source)?,
+                "Section" => parse_section(entry, &mut this.section)?,
+                "Provides" => parse_names(entry, &mut this.provides)?,
+                "Filename" => parse_string(entry, &mut this.filename)?,
                 _ => {}
             }
         }
@@ -68,12 +212,55 @@ impl TryFrom<Control<'_>> for Package {
     }
 }
 
+impl Package {
+    // Name and version of the source this was built from. The Source field only has a version
+    // when it differs from the binary version, like for binNMUs.
+    pub fn source_version(&self) -> Option<(&str, &str)> {
+        let version = self.version.as_deref()?;
+        let Some(source) = self.source.as_deref() else {
+            return Some((self.package.as_deref()?, strip_binnmu(version)));
+        };
+        match source.split_once(" (") {
+            Some((name, source_version)) => Some((
+                name,
+                source_version.strip_suffix(')').unwrap_or(source_version),
+            )),
+            None => Some((source, strip_binnmu(version))),
+        }
+    }
+}
+
+// Encodes each segment of a path built from suites, components, and pool paths, keeping the
+// slashes between them. Joining them as they are would turn a # or ? in a misconfigured
+// component into a fragment or query, and request some other path that is then not found.
+pub fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| urlencoding::encode(segment))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Removes a binary-only revision like +b1 from a version, leaving the source version
+fn strip_binnmu(version: &str) -> &str {
+    match version.rsplit_once("+b") {
+        Some((source_version, revision))
+            if !revision.is_empty() && revision.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            source_version
+        }
+        _ => version,
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Source {
     pub package: Option<String>,
     pub archs: Option<Vec<String>>,
     pub version: Option<String>,
     pub directory: Option<String>,
+    pub section: Option<String>,
+    pub vcs_git: Option<String>,
+    pub maintainer: Option<String>,
 }
 
 impl TryFrom<Control<'_>> for Source {
@@ -86,6 +273,9 @@ impl TryFrom<Control<'_>> for Source {
                 "Architectures" => parse_array(entry, &mut this.archs)?,
                 "Version" => parse_string(entry, &mut this.version)?,
                 "Directory" => parse_string(entry, &mut this.directory)?,
+                "Vcs-Git" => parse_string(entry, &mut this.vcs_git)?,
+                "Section" => parse_section(entry, &mut this.section)?,
+                "Maintainer" => parse_string(entry, &mut this.maintainer)?,
                 _ => {}
             }
         }
@@ -93,85 +283,1029 @@ impl TryFrom<Control<'_>> for Source {
     }
 }
 
+impl Source {
+    // Branch from a Vcs-Git field like "https://github.com/pop-os/foo.git -b master_noble"
+    pub fn vcs_branch(&self) -> Option<&str> {
+        let mut parts = self.vcs_git.as_deref()?.split_whitespace();
+        while let Some(part) = parts.next() {
+            if part == "-b" {
+                return parts.next();
+            }
+        }
+        None
+    }
+}
+
+// Compression of an index file, based on its file name
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Bzip2 => ".bz2",
+        }
+    }
+}
+
+// Compressed forms of an index to try, in order of preference
+const INDEX_COMPRESSIONS: &[Compression] =
+    &[Compression::Gzip, Compression::Bzip2, Compression::None];
+
+// Bytes of index data received, and after decompression
+static WIRE_BYTES: AtomicU64 = AtomicU64::new(0);
+static DECODED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+// Returns the total bytes of index data downloaded so far, as received and after decompression
+pub fn downloaded_bytes() -> (u64, u64) {
+    (
+        WIRE_BYTES.load(atomic::Ordering::Relaxed),
+        DECODED_BYTES.load(atomic::Ordering::Relaxed),
+    )
+}
+
+// Downloads at least this large log their progress at debug level
+const PROGRESS_MIN_BYTES: u64 = 4 * 1024 * 1024;
+const PROGRESS_STEP_PERCENT: u64 = 10;
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Retries left for the whole run, so a mirror that is down fails the run quickly instead of
+// every request waiting to retry
+static RETRY_BUDGET: AtomicU64 = AtomicU64::new(u64::MAX);
+static RETRY_BUDGET_LOGGED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_retry_budget(retries: u64) {
+    RETRY_BUDGET.store(retries, atomic::Ordering::Relaxed);
+}
+
+// Takes a retry from the budget, returning false once it is used up
+fn take_retry() -> bool {
+    let taken = RETRY_BUDGET
+        .fetch_update(
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+            |left| left.checked_sub(1),
+        )
+        .is_ok();
+    if !taken && !RETRY_BUDGET_LOGGED.swap(true, atomic::Ordering::Relaxed) {
+        log::error!("retry budget exhausted, transient errors are no longer retried");
+    }
+    taken
+}
+
+// Redirects to follow before giving up, matching the reqwest default
+const MAX_REDIRECTS: usize = 10;
+
+// Whether a host may be contacted, an empty list allows every host
+pub fn host_allowed(allowed_hosts: &[String], host: Option<&str>) -> bool {
+    allowed_hosts.is_empty()
+        || host.is_some_and(|host| {
+            allowed_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        })
+}
+
+// Follows redirects, logging the ones that leave the original host since they usually mean a
+// mirror moved rather than a load balancer picking a backend. Redirects to hosts that are not
+// allowed are refused.
+fn redirect_policy(allowed_hosts: Vec<String>, insecure_hosts: Vec<String>) -> redirect::Policy {
+    redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        if !host_allowed(&allowed_hosts, attempt.url().host_str()) {
+            let message = format!(
+                "redirect to {} refused, host not in allowed_hosts",
+                attempt.url()
+            );
+            return attempt.error(message);
+        }
+        // The insecure client must not carry a redirect to a host that was not opted in
+        if !insecure_hosts.is_empty() && !host_allowed(&insecure_hosts, attempt.url().host_str()) {
+            let message = format!(
+                "redirect to {} refused, host not in insecure_hosts",
+                attempt.url()
+            );
+            return attempt.error(message);
+        }
+        let from = &attempt.previous()[0];
+        if from.host_str() != attempt.url().host_str() {
+            log::info!("{from} redirected to {}", attempt.url());
+        }
+        attempt.follow()
+    })
+}
+
+// Resolves host names with the system resolver, reusing results for a while so that slow DNS
+// is only paid once per host instead of for every new connection
+struct DnsCache {
+    ttl: Duration,
+    entries: Arc<Mutex<DnsEntries>>,
+}
+
+// When each host was resolved and its addresses
+type DnsEntries = HashMap<String, (Instant, Vec<SocketAddr>)>;
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let name = name.as_str().to_string();
+        let ttl = self.ttl;
+        let entries = self.entries.clone();
+        Box::pin(async move {
+            if let Some((resolved, addrs)) = entries.lock().unwrap().get(&name)
+                && resolved.elapsed() < ttl
+            {
+                log::debug!("{name}: using cached DNS result");
+                let addrs: Addrs = Box::new(addrs.clone().into_iter());
+                return Ok(addrs);
+            }
+            log::debug!("{name}: resolving");
+            let resolved: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            entries
+                .lock()
+                .unwrap()
+                .insert(name, (Instant::now(), resolved.clone()));
+            let addrs: Addrs = Box::new(resolved.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+// One client for every repo, so connections to hosts shared by several repos are pooled
+static CLIENT: OnceLock<Client> = OnceLock::new();
+static INSECURE_CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn client_builder(config: &Config, insecure_hosts: Vec<String>) -> ClientBuilder {
+    let mut builder = Client::builder()
+        .redirect(redirect_policy(
+            config.allowed_hosts.clone(),
+            insecure_hosts,
+        ))
+        .pool_idle_timeout(Duration::from_secs(config.http.pool_idle_timeout))
+        .pool_max_idle_per_host(config.http.pool_max_idle_per_host)
+        .tls_version_min(match config.http.min_tls_version {
+            TlsVersion::Tls12 => tls::Version::TLS_1_2,
+            TlsVersion::Tls13 => tls::Version::TLS_1_3,
+        });
+    if config.http.dns_cache_ttl > 0 {
+        builder = builder.dns_resolver(DnsCache {
+            ttl: Duration::from_secs(config.http.dns_cache_ttl),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        });
+    }
+    builder
+}
+
+// Returns the client for requests to the host of url, which ignores certificate errors only for
+// insecure_hosts
+pub fn client_for(config: &Config, url: &Url) -> Result<Client> {
+    if config.insecure_host(url.host_str()) {
+        insecure_client(config)
+    } else {
+        shared_client(config)
+    }
+}
+
+// Returns the client shared by all repos, building it from the config on first use
+fn shared_client(config: &Config) -> Result<Client> {
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = client_builder(config, Vec::new())
+        .build()
+        .context("failed to build HTTP client")?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+// Returns the client for repos on a host in insecure_hosts, which accepts any certificate. It is
+// kept apart from the shared client and only follows redirects to other insecure hosts, so
+// certificates are still checked everywhere else.
+fn insecure_client(config: &Config) -> Result<Client> {
+    if let Some(client) = INSECURE_CLIENT.get() {
+        return Ok(client.clone());
+    }
+    log::warn!(
+        "TLS CERTIFICATES ARE NOT CHECKED for {}, remove them from insecure_hosts once fixed",
+        config.http.insecure_hosts.join(", ")
+    );
+    let client = client_builder(config, config.http.insecure_hosts.clone())
+        .tls_danger_accept_invalid_certs(true)
+        .build()
+        .context("failed to build HTTP client")?;
+    Ok(INSECURE_CLIENT.get_or_init(|| client).clone())
+}
+
+// Finds a TLS error, like an expired or untrusted certificate, among the causes of an error.
+// The TLS error is wrapped in io::Errors, whose source skips over what they wrap, so those are
+// unwrapped directly.
+fn tls_error<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a rustls::Error> {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(tls_err) = err.downcast_ref::<rustls::Error>() {
+            return Some(tls_err);
+        }
+        source = match err
+            .downcast_ref::<std::io::Error>()
+            .and_then(|io_err| io_err.get_ref())
+        {
+            Some(inner) => Some(inner as &(dyn std::error::Error + 'static)),
+            None => err.source(),
+        };
+    }
+    None
+}
+
+// An index whose length differs from its size in the Release, like from a mirror that is part
+// way through a sync or a truncated transfer
+#[derive(Debug)]
+struct SizeMismatch {
+    path: String,
+    received: u64,
+    expected: u64,
+}
+
+impl fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: received {} bytes, Release lists {}",
+            self.path, self.received, self.expected
+        )
+    }
+}
+
+impl std::error::Error for SizeMismatch {}
+
+// Why fetching an index failed, which decides whether to retry or try another format
+enum FetchFailure {
+    // The server does not have this file
+    NotFound,
+    // Network errors and server errors that may succeed on retry
+    Transient,
+    // Anything else, like a parse error
+    Fatal,
+}
+
+impl FetchFailure {
+    fn classify(err: &anyhow::Error) -> Self {
+        // A bad certificate will not fix itself by retrying
+        if err.chain().any(|err| tls_error(err).is_some()) {
+            return Self::Fatal;
+        }
+        // A mirror part way through a sync may have finished by the retry
+        if err.downcast_ref::<SizeMismatch>().is_some() {
+            return Self::Transient;
+        }
+        // Errors while streaming the body are wrapped in io::Error
+        let reqwest_err = err.downcast_ref::<reqwest::Error>().or_else(|| {
+            err.downcast_ref::<std::io::Error>()?
+                .get_ref()?
+                .downcast_ref::<reqwest::Error>()
+        });
+        let Some(reqwest_err) = reqwest_err else {
+            return Self::Fatal;
+        };
+        match reqwest_err.status() {
+            Some(StatusCode::NOT_FOUND) => Self::NotFound,
+            Some(status) if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                Self::Transient
+            }
+            Some(_) => Self::Fatal,
+            None if reqwest_err.is_builder() || reqwest_err.is_redirect() => Self::Fatal,
+            None => Self::Transient,
+        }
+    }
+}
+
+// Splits an index into stanzas like ControlDecoder, but fails once a stanza grows past a limit
+// instead of buffering it without bound
+struct LimitedControlDecoder {
+    max_stanza_bytes: u64,
+}
+
+impl LimitedControlDecoder {
+    fn check(&self, len: usize) -> std::io::Result<()> {
+        if len as u64 > self.max_stanza_bytes {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "stanza larger than max_stanza_bytes of {}",
+                    self.max_stanza_bytes
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for LimitedControlDecoder {
+    type Item = BytesMut;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> std::io::Result<Option<BytesMut>> {
+        match ControlDecoder.decode(src)? {
+            Some(stanza) => {
+                self.check(stanza.len())?;
+                Ok(Some(stanza))
+            }
+            // What is left is part of one stanza, which is already too large if over the limit
+            None => {
+                self.check(src.len())?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> std::io::Result<Option<BytesMut>> {
+        let stanza = ControlDecoder.decode_eof(src)?;
+        if let Some(stanza) = &stanza {
+            self.check(stanza.len())?;
+        }
+        Ok(stanza)
+    }
+}
+
 #[derive(Clone)]
 pub struct AptRepo {
+    client: Client,
     url: Url,
+    allowed_hosts: Vec<String>,
+    max_stanza_bytes: u64,
+    max_index_bytes: u64,
+    // Extra headers from the repo config, marked sensitive so reqwest does not log them
+    headers: HeaderMap,
+    min_tls_version: TlsVersion,
+    verify_index_sizes: bool,
+    dump: Option<String>,
 }
 
 impl AptRepo {
-    pub fn new(url: Url) -> Self {
-        Self { url }
+    pub fn new(config: &Config, repo_kind: RepoKind) -> Result<Self> {
+        let url = config.repo_url(repo_kind);
+        let client = client_for(config, &url)?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in config.headers(repo_kind).into_iter().flatten() {
+            let name = HeaderName::from_bytes(name.as_bytes())?;
+            let mut value = HeaderValue::from_str(value.expose())
+                .with_context(|| format!("invalid value for header {name}"))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+        if !headers.is_empty() {
+            let names: Vec<_> = headers.keys().map(|name| name.as_str()).collect();
+            log::debug!(
+                "{}: sending headers {}",
+                repo_kind.as_str(),
+                names.join(", ")
+            );
+        }
+        Ok(Self {
+            client,
+            url,
+            allowed_hosts: config.allowed_hosts.clone(),
+            max_stanza_bytes: config.max_stanza_bytes,
+            max_index_bytes: config.max_index_bytes,
+            headers,
+            min_tls_version: config.http.min_tls_version,
+            verify_index_sizes: config.verify_index_sizes,
+            dump: None,
+        })
+    }
+
+    // Print stanzas parsed for this package to stderr, for debugging
+    pub fn with_dump(mut self, package: Option<String>) -> Self {
+        self.dump = package;
+        self
+    }
+
+    // URL of a path below the repo, with each segment encoded
+    fn url(&self, path: &str) -> Result<Url> {
+        Ok(self.url.join(&encode_path(path))?)
     }
 
     async fn get(&self, path: &str) -> Result<reqwest::Response> {
-        let url = self.url.join(path)?;
-        let response = reqwest::get(url).await?.error_for_status()?;
-        Ok(response)
+        self.send(Method::GET, path).await
+    }
+
+    async fn send(&self, method: Method, path: &str) -> Result<reqwest::Response> {
+        let url = self.url(path)?;
+        if !host_allowed(&self.allowed_hosts, url.host_str()) {
+            return Err(anyhow!("{url}: host not in allowed_hosts"));
+        }
+        let response = match self
+            .client
+            .request(method, url.clone())
+            .headers(self.headers.clone())
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            // Named apart from other connection errors, as it needs a fix on the mirror
+            Err(err) => {
+                return Err(match tls_error(&err) {
+                    Some(tls_err) => {
+                        let mut message = format!(
+                            "TLS error for {}: {tls_err}",
+                            url.host_str().unwrap_or_default()
+                        );
+                        // Never retried with an older version, the policy is the whole point
+                        if matches!(
+                            tls_err,
+                            rustls::Error::PeerIncompatible(_)
+                                | rustls::Error::AlertReceived(
+                                    rustls::AlertDescription::ProtocolVersion
+                                )
+                        ) {
+                            message.push_str(&format!(
+                                ", the server does not support TLS {} or later required by http.min_tls_version",
+                                self.min_tls_version.as_str()
+                            ));
+                        }
+                        anyhow::Error::new(err).context(message)
+                    }
+                    None => err.into(),
+                });
+            }
+        };
+        Ok(response.error_for_status()?)
     }
 
+    // Fetches and parses a control file. With expected_size, from the Release, and
+    // verify_index_sizes, the body must be exactly that long.
+    #[tracing::instrument(skip_all)]
     async fn get_control<T, F: Fn(Control) -> Result<T>>(
         &self,
         path: &str,
+        compression: Compression,
+        expected_size: Option<u64>,
         map_control: F,
     ) -> Result<Vec<T>> {
-        let response = self.get(path).await?;
+        let response = self
+            .get(path)
+            .instrument(tracing::info_span!("request"))
+            .await?;
+        // Some mirrors add transport compression, which reqwest does not decode for us. It is
+        // stripped before decoding by extension, except for a .gz served with Content-Encoding:
+        // gzip, which is still only compressed once and must not be decoded twice.
+        let transport_gzip = match response.headers().get(CONTENT_ENCODING) {
+            Some(encoding) => match encoding.to_str() {
+                Ok("gzip" | "x-gzip") => compression != Compression::Gzip,
+                Ok("identity") => false,
+                _ => {
+                    return Err(anyhow!(
+                        "{}: unsupported Content-Encoding {:?}",
+                        response.url(),
+                        encoding
+                    ));
+                }
+            },
+            None => false,
+        };
+        // Transport compression changes the length of the body
+        let expected_size = expected_size.filter(|_| self.verify_index_sizes && !transport_gzip);
+        // Only report progress of large downloads with a known length, chunked responses are
+        // left alone rather than guessing
+        let progress_len = response
+            .content_length()
+            .filter(|len| *len >= PROGRESS_MIN_BYTES);
+        let progress_url = response.url().clone();
+        let mut received = 0;
+        let mut next_percent = PROGRESS_STEP_PERCENT;
+        let body_bytes = Arc::new(AtomicU64::new(0));
         let stream = response
             .bytes_stream()
+            .inspect_ok({
+                let body_bytes = body_bytes.clone();
+                move |chunk| {
+                    WIRE_BYTES.fetch_add(chunk.len() as u64, atomic::Ordering::Relaxed);
+                    body_bytes.fetch_add(chunk.len() as u64, atomic::Ordering::Relaxed);
+                    let Some(len) = progress_len else {
+                        return;
+                    };
+                    received += chunk.len() as u64;
+                    let percent = received * 100 / len;
+                    if percent >= next_percent {
+                        log::debug!("{progress_url}: {percent}% of {len} bytes");
+                        next_percent =
+                            percent - percent % PROGRESS_STEP_PERCENT + PROGRESS_STEP_PERCENT;
+                    }
+                }
+            })
             .map_err(std::io::Error::other)
             .into_async_read();
-        let mut control_stream = FramedRead::new(stream, ControlDecoder::default());
+        let stream: Pin<Box<dyn AsyncBufRead + Send>> = if transport_gzip {
+            Box::pin(BufReader::new(GzipDecoder::new(stream)))
+        } else {
+            Box::pin(stream)
+        };
+        // Some tools write indices as several concatenated gzip members or bzip2 streams, and
+        // decoders stop after the first one by default, which would silently drop stanzas
+        let reader: Pin<Box<dyn AsyncRead + Send>> = match compression {
+            Compression::None => Box::pin(stream),
+            Compression::Gzip => {
+                let mut decoder = GzipDecoder::new(stream);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Compression::Bzip2 => {
+                let mut decoder = BzDecoder::new(stream);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+        };
+        // Every stage is pulled by the one after it, so the body is only read as fast as stanzas
+        // are parsed, and each fetch buffers at most one stanza besides the parsed results.
+        // Collecting the body or decoded index into memory first would lose this.
+        let decoder = LimitedControlDecoder {
+            max_stanza_bytes: self.max_stanza_bytes,
+        };
+        let mut control_stream = FramedRead::new(reader, decoder);
         //TODO: return mapped stream
         let mut res = Vec::new();
-        while let Some(event) = control_stream.next().await {
-            let event = event.unwrap();
+        let mut index_bytes = 0;
+        // Reading the body happens as stanzas are pulled, so it is part of decoding
+        while let Some(event) = control_stream
+            .next()
+            .instrument(tracing::info_span!("decode"))
+            .await
+        {
+            let event = event.with_context(|| format!("failed to read {path}"))?;
+            DECODED_BYTES.fetch_add(event.len() as u64, atomic::Ordering::Relaxed);
+            index_bytes += event.len() as u64;
+            if index_bytes > self.max_index_bytes {
+                return Err(anyhow!(
+                    "{path}: index larger than max_index_bytes of {}",
+                    self.max_index_bytes
+                ));
+            }
             let event = str::from_utf8(&event).expect("not UTF8");
-            res.push(map_control(Control::new(&event))?);
+            if let Some(package) = &self.dump
+                && Control::new(event).any(|entry| entry.key == "Package" && entry.value == package)
+            {
+                eprintln!("{}:", self.url(path)?);
+                for entry in Control::new(event) {
+                    eprintln!("\t{}: {}", entry.key, entry.value);
+                }
+            }
+            res.push(tracing::info_span!("parse").in_scope(|| map_control(Control::new(event)))?);
         }
 
+        let received = body_bytes.load(atomic::Ordering::Relaxed);
+        if let Some(expected) = expected_size
+            && received != expected
+        {
+            return Err(SizeMismatch {
+                path: path.to_string(),
+                received,
+                expected,
+            }
+            .into());
+        }
         Ok(res)
     }
 
-    //TODO: reduce code replication with get_control
-    async fn get_control_gzip<T, F: Fn(Control) -> Result<T>>(
+    // Fetches an index, trying each compressed form until one exists. Only a 404 moves on to
+    // the next form, a transient failure is retried once and then reported, so that a flaky
+    // mirror is not mistaken for a missing format.
+    async fn get_index<T, F: Fn(Control) -> Result<T>>(
         &self,
-        path: &str,
+        suite: &str,
+        index: &str,
+        sizes: &IndexSizes,
         map_control: F,
     ) -> Result<Vec<T>> {
-        let response = self.get(path).await?;
-        let stream = response.bytes_stream().map_err(std::io::Error::other);
-        let stream = GzipDecoder::new(stream.into_async_read());
-        let mut control_stream = FramedRead::new(stream, ControlDecoder::default());
-        //TODO: return mapped stream
-        let mut res = Vec::new();
-        while let Some(event) = control_stream.next().await {
-            let event = event.unwrap();
-            let event = str::from_utf8(&event).expect("not UTF8");
-            res.push(map_control(Control::new(&event))?);
+        let path = format!("dists/{suite}/{index}");
+        let mut last_err = None;
+        for compression in INDEX_COMPRESSIONS {
+            let compressed_path = format!("{path}{}", compression.extension());
+            let expected_size = sizes
+                .get(&format!("{index}{}", compression.extension()))
+                .copied();
+            let mut retried = false;
+            loop {
+                let err = match self
+                    .get_control(&compressed_path, *compression, expected_size, &map_control)
+                    .await
+                {
+                    Ok(ok) => return Ok(ok),
+                    Err(err) => err,
+                };
+                match FetchFailure::classify(&err) {
+                    FetchFailure::NotFound => {
+                        log::debug!("{compressed_path} not found, trying next format");
+                        last_err = Some(err);
+                        break;
+                    }
+                    FetchFailure::Transient if !retried && take_retry() => {
+                        log::warn!("{compressed_path} failed, retrying: {err:#}");
+                        retried = true;
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    }
+                    FetchFailure::Transient | FetchFailure::Fatal => return Err(err),
+                }
+            }
         }
+        Err(last_err.unwrap_or_else(|| anyhow!("{path}: no index formats to try")))
+    }
 
-        Ok(res)
+    // Whether a file is in the pool, checked with a HEAD request so nothing is downloaded
+    pub async fn pool_file_exists(&self, path: &str) -> Result<bool> {
+        match self.send(Method::HEAD, path).await {
+            Ok(_response) => Ok(true),
+            Err(err) if matches!(FetchFailure::classify(&err), FetchFailure::NotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
     }
 
     pub async fn release(&self, suite: &str) -> Result<Vec<Release>> {
-        self.get_control(&format!("dists/{suite}/Release"), |control| {
-            Release::try_from(control)
-        })
+        self.get_control(
+            &format!("dists/{suite}/Release"),
+            Compression::None,
+            None,
+            |control| Release::try_from(control),
+        )
         .await
     }
 
-    pub async fn packages(&self, suite: &str, component: &str, arch: &str) -> Result<Vec<Package>> {
-        self.get_control_gzip(
-            &format!("dists/{suite}/{component}/binary-{arch}/Packages.gz"),
+    pub async fn packages(
+        &self,
+        suite: &str,
+        component: &str,
+        arch: &str,
+        sizes: &IndexSizes,
+    ) -> Result<Vec<Package>> {
+        self.get_index(
+            suite,
+            &format!("{component}/binary-{arch}/Packages"),
+            sizes,
             |control| Package::try_from(control),
         )
         .await
     }
 
-    pub async fn sources(&self, suite: &str, component: &str) -> Result<Vec<Source>> {
-        self.get_control_gzip(
-            &format!("dists/{suite}/{component}/source/Sources.gz"),
-            |control| Source::try_from(control),
-        )
-        .await
+    // Fetches the Sources of a component, merging the split indices from
+    // Release::split_sources if there are any
+    pub async fn sources(
+        &self,
+        suite: &str,
+        component: &str,
+        split: &[String],
+        sizes: &IndexSizes,
+    ) -> Result<Vec<Source>> {
+        if split.is_empty() {
+            return self
+                .get_index(
+                    suite,
+                    &format!("{component}/source/Sources"),
+                    sizes,
+                    |control| Source::try_from(control),
+                )
+                .await;
+        }
+        let mut sources = Vec::new();
+        for index in split {
+            sources.extend(
+                self.get_index(suite, index, sizes, |control| Source::try_from(control))
+                    .await?,
+            );
+        }
+        Ok(sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+    use bzip2::write::BzEncoder;
+    use flate2::{Compression as Level, write::GzEncoder};
+    use std::io::Write;
+
+    const SOURCES: &str = "Package: foo\nVersion: 1.0\n\nPackage: bar\nVersion: 2.0\n";
+
+    fn repo(server: &TestServer, overrides: &[&str]) -> AptRepo {
+        // Later overrides win, so tests can point the repo below the server root
+        let mut overrides: Vec<_> = overrides.iter().map(|x| x.to_string()).collect();
+        overrides.insert(0, format!("repos.release.url={}", server.url));
+        let config = Config::load(&[] as &[&str], &overrides).unwrap();
+        AptRepo::new(&config, RepoKind::Release).unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn packages(sources: &[Source]) -> Vec<&str> {
+        sources
+            .iter()
+            .map(|source| source.package.as_deref().unwrap())
+            .collect()
+    }
+
+    async fn sources(repo: &AptRepo) -> Result<Vec<Source>> {
+        repo.sources("noble", "main", &[], &IndexSizes::new()).await
+    }
+
+    #[tokio::test]
+    async fn transport_gzip_of_uncompressed_index_is_decoded() {
+        let server = TestServer::start(vec![(
+            "/dists/noble/main/source/Sources",
+            vec![Response::ok(gzip(SOURCES.as_bytes())).header("Content-Encoding", "gzip")],
+        )])
+        .await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+    }
+
+    #[tokio::test]
+    async fn transport_gzip_of_bzip2_index_is_decoded_by_extension() {
+        let server = TestServer::start(vec![(
+            "/dists/noble/main/source/Sources.bz2",
+            vec![Response::ok(gzip(&bzip2(SOURCES.as_bytes()))).header("Content-Encoding", "gzip")],
+        )])
+        .await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+        assert_eq!(
+            server.requests(),
+            [
+                "GET /dists/noble/main/source/Sources.gz",
+                "GET /dists/noble/main/source/Sources.bz2"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn gzip_index_with_gzip_content_encoding_is_decoded_once() {
+        let server = TestServer::start(vec![(
+            "/dists/noble/main/source/Sources.gz",
+            vec![Response::ok(gzip(SOURCES.as_bytes())).header("Content-Encoding", "gzip")],
+        )])
+        .await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+    }
+
+    const SOURCES_PATH: &str = "/dists/noble/main/source/Sources";
+
+    async fn classify(status: u16) -> FetchFailure {
+        let server = TestServer::start(vec![("/file", vec![Response::status(status)])]).await;
+        let err = repo(&server, &[]).get("file").await.unwrap_err();
+        FetchFailure::classify(&err)
+    }
+
+    #[tokio::test]
+    async fn classify_by_status() {
+        assert!(matches!(classify(404).await, FetchFailure::NotFound));
+        assert!(matches!(classify(503).await, FetchFailure::Transient));
+        assert!(matches!(classify(429).await, FetchFailure::Transient));
+        assert!(matches!(classify(403).await, FetchFailure::Fatal));
+    }
+
+    #[test]
+    fn classify_other_errors() {
+        let mismatch = anyhow::Error::from(SizeMismatch {
+            path: "Sources".to_string(),
+            received: 1,
+            expected: 2,
+        });
+        assert!(matches!(
+            FetchFailure::classify(&mismatch.context("failed")),
+            FetchFailure::Transient
+        ));
+        assert!(matches!(
+            FetchFailure::classify(&anyhow!("entry Package already set")),
+            FetchFailure::Fatal
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_formats_fall_back_in_order() {
+        let server = TestServer::start(vec![(SOURCES_PATH, vec![Response::ok(SOURCES)])]).await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+        assert_eq!(
+            server.requests(),
+            [
+                "GET /dists/noble/main/source/Sources.gz",
+                "GET /dists/noble/main/source/Sources.bz2",
+                "GET /dists/noble/main/source/Sources"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn transient_failure_is_retried_without_falling_back() {
+        let server = TestServer::start(vec![(
+            "/dists/noble/main/source/Sources.gz",
+            vec![
+                Response::status(503),
+                Response::ok(gzip(SOURCES.as_bytes())),
+            ],
+        )])
+        .await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+        assert_eq!(
+            server.requests(),
+            [
+                "GET /dists/noble/main/source/Sources.gz",
+                "GET /dists/noble/main/source/Sources.gz"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn repeated_transient_failure_is_reported() {
+        let server = TestServer::start(vec![
+            (
+                "/dists/noble/main/source/Sources.gz",
+                vec![Response::status(503)],
+            ),
+            (SOURCES_PATH, vec![Response::ok(SOURCES)]),
+        ])
+        .await;
+        let err = sources(&repo(&server, &[])).await.unwrap_err();
+        assert!(matches!(
+            FetchFailure::classify(&err),
+            FetchFailure::Transient
+        ));
+        assert_eq!(
+            server.requests(),
+            [
+                "GET /dists/noble/main/source/Sources.gz",
+                "GET /dists/noble/main/source/Sources.gz"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn all_formats_missing_reports_the_last() {
+        let server = TestServer::start(Vec::new()).await;
+        let err = sources(&repo(&server, &[])).await.unwrap_err();
+        assert!(matches!(
+            FetchFailure::classify(&err),
+            FetchFailure::NotFound
+        ));
+        assert_eq!(server.requests().len(), INDEX_COMPRESSIONS.len());
+        assert!(format!("{err:#}").contains("Sources"), "{err:#}");
+    }
+
+    fn decoder(max_stanza_bytes: u64) -> LimitedControlDecoder {
+        LimitedControlDecoder { max_stanza_bytes }
+    }
+
+    #[test]
+    fn decode_stops_at_stanza_limit() {
+        let mut src = BytesMut::from("Package: foo\n\nPackage: foobar\n\n");
+        let mut decoder = decoder(14);
+        assert_eq!(decoder.decode(&mut src).unwrap().unwrap().len(), 14);
+        let err = decoder.decode(&mut src).unwrap_err();
+        assert!(err.to_string().contains("max_stanza_bytes of 14"), "{err}");
+    }
+
+    #[test]
+    fn decode_stops_at_stanza_limit_before_stanza_ends() {
+        let mut src = BytesMut::from("Package: foo\nVersion: 1.0\n");
+        assert!(decoder(14).decode(&mut src).is_err());
+        let mut src = BytesMut::from("Package: foo\nVersion: 1.0\n");
+        assert!(decoder(100).decode(&mut src).unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_eof_stops_at_stanza_limit() {
+        let mut src = BytesMut::from("Package: foobar");
+        assert!(decoder(14).decode_eof(&mut src).is_err());
+        let mut src = BytesMut::from("Package: foo");
+        assert_eq!(decoder(14).decode_eof(&mut src).unwrap().unwrap().len(), 12);
+    }
+
+    #[tokio::test]
+    async fn index_limit_stops_fetch() {
+        let server = TestServer::start(vec![(SOURCES_PATH, vec![Response::ok(SOURCES)])]).await;
+        let limited = repo(&server, &["max_stanza_bytes=30", "max_index_bytes=30"]);
+        let err = sources(&limited).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("larger than max_index_bytes of 30"),
+            "{err}"
+        );
+        let large_enough = repo(&server, &["max_stanza_bytes=30", "max_index_bytes=60"]);
+        assert_eq!(
+            packages(&sources(&large_enough).await.unwrap()),
+            ["foo", "bar"]
+        );
+    }
+
+    #[tokio::test]
+    async fn bzip2_index_is_decoded() {
+        let server = TestServer::start(vec![(
+            "/dists/noble/main/source/Sources.bz2",
+            vec![Response::ok(bzip2(SOURCES.as_bytes()))],
+        )])
+        .await;
+        let sources = sources(&repo(&server, &[])).await.unwrap();
+        assert_eq!(packages(&sources), ["foo", "bar"]);
+        let versions: Vec<_> = sources
+            .iter()
+            .map(|source| source.version.as_deref().unwrap())
+            .collect();
+        assert_eq!(versions, ["1.0", "2.0"]);
+    }
+
+    #[tokio::test]
+    async fn concatenated_members_are_all_decoded() {
+        let (first, second) = SOURCES.split_at(SOURCES.find("Package: bar").unwrap());
+        let gzip_members = [gzip(first.as_bytes()), gzip(second.as_bytes())].concat();
+        let bzip2_streams = [bzip2(first.as_bytes()), bzip2(second.as_bytes())].concat();
+        let server = TestServer::start(vec![
+            (
+                "/dists/noble/main/source/Sources.gz",
+                vec![Response::ok(gzip_members)],
+            ),
+            (
+                "/dists/resolute/main/source/Sources.bz2",
+                vec![Response::ok(bzip2_streams)],
+            ),
+        ])
+        .await;
+        let repo = repo(&server, &[]);
+        for suite in ["noble", "resolute"] {
+            let sources = repo
+                .sources(suite, "main", &[], &IndexSizes::new())
+                .await
+                .unwrap();
+            assert_eq!(packages(&sources), ["foo", "bar"], "{suite}");
+        }
+    }
+
+    #[tokio::test]
+    async fn paths_are_joined_below_repo_url() {
+        let server = TestServer::start(vec![(
+            "/ubuntu/20250101T000000Z/dists/noble/main/source/Sources",
+            vec![Response::ok(SOURCES)],
+        )])
+        .await;
+        for url in ["ubuntu/20250101T000000Z", "ubuntu/20250101T000000Z/"] {
+            let repo = repo(
+                &server,
+                &[&format!("repos.release.url={}{url}", server.url)],
+            );
+            assert_eq!(
+                repo.url("dists/noble/Release").unwrap().path(),
+                "/ubuntu/20250101T000000Z/dists/noble/Release"
+            );
+            assert_eq!(packages(&sources(&repo).await.unwrap()), ["foo", "bar"]);
+        }
+    }
+
+    #[test]
+    fn encode_path_keeps_slashes_and_encodes_segments() {
+        assert_eq!(
+            encode_path("pool/main/f/foo/foo_1.0+b1~22.04.dsc"),
+            "pool/main/f/foo/foo_1.0%2Bb1~22.04.dsc"
+        );
+        assert_eq!(
+            encode_path("dists/noble/odd comp#1/Release"),
+            "dists/noble/odd%20comp%231/Release"
+        );
+        let url = Url::parse("https://apt.example.com/ubuntu/").unwrap();
+        assert_eq!(
+            url.join(&encode_path("pool/main/f/foo/foo 1+2~3.dsc"))
+                .unwrap()
+                .as_str(),
+            "https://apt.example.com/ubuntu/pool/main/f/foo/foo%201%2B2~3.dsc"
+        );
+    }
+
+    fn release_valid_until(valid_until: &str) -> Release {
+        Release {
+            valid_until: Some(valid_until.to_string()),
+            ..Release::default()
+        }
+    }
+
+    #[test]
+    fn release_expires_after_valid_until() {
+        assert!(release_valid_until("Sat, 01 Jan 2000 00:00:00 UTC").expired());
+        assert!(!release_valid_until("Fri, 01 Jan 2100 00:00:00 UTC").expired());
+        assert!(!Release::default().expired());
     }
 }
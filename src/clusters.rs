@@ -0,0 +1,100 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use crate::{AptInfos, Args, Severity, config::*, filtered_apt_infos, table::print_table};
+
+// Errors of one severity on versions from the same repo, suite, and component. A large cluster
+// usually has a single cause, like a component that failed to publish to the next repo.
+#[derive(Serialize)]
+pub struct Cluster {
+    pub repo: &'static str,
+    pub suite: String,
+    pub component: String,
+    pub severity: Severity,
+    pub errors: usize,
+    // Message of the first error found, as errors in a cluster mostly read the same
+    pub example: String,
+}
+
+impl Cluster {
+    // Like "Release noble main", leaving out the suite and component of models saved before they
+    // were recorded
+    pub fn location(&self) -> String {
+        let mut location = self.repo.to_string();
+        for part in [&self.suite, &self.component] {
+            if !part.is_empty() {
+                location.push(' ');
+                location.push_str(part);
+            }
+        }
+        location
+    }
+}
+
+// Groups the errors of every version, largest clusters first
+pub fn error_clusters(apt_infos: &AptInfos) -> Vec<Cluster> {
+    let mut clusters = BTreeMap::<(RepoKind, &str, &str, Severity), Cluster>::new();
+    for apt_info in apt_infos.values() {
+        for repo_kind in RepoKind::all() {
+            let Some(version) = apt_info.version(repo_kind) else {
+                continue;
+            };
+            for error in version.errors.borrow().iter() {
+                let key = (
+                    repo_kind,
+                    version.suite.as_str(),
+                    version.component.as_str(),
+                    error.severity,
+                );
+                clusters
+                    .entry(key)
+                    .or_insert_with(|| Cluster {
+                        repo: repo_kind.id(),
+                        suite: version.suite.clone(),
+                        component: version.component.clone(),
+                        severity: error.severity,
+                        errors: 0,
+                        example: error.message.clone(),
+                    })
+                    .errors += 1;
+            }
+        }
+    }
+    let mut clusters: Vec<_> = clusters.into_values().collect();
+    // Stable, so clusters of the same size stay in repo and suite order
+    clusters.sort_by_key(|cluster| Reverse(cluster.errors));
+    clusters
+}
+
+// Prints every error cluster, largest first, to find the few causes behind many errors
+pub async fn clusters(args: &Args, config: &Config, json: bool) -> Result<()> {
+    let apt_infos = filtered_apt_infos(args, config).await?.apt_infos;
+    let clusters = error_clusters(&apt_infos);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&clusters)?);
+        return Ok(());
+    }
+
+    let mut table = vec![vec![
+        "Repo".to_string(),
+        "Suite".to_string(),
+        "Component".to_string(),
+        "Severity".to_string(),
+        "Errors".to_string(),
+        "Example".to_string(),
+    ]];
+    for cluster in clusters.iter() {
+        table.push(vec![
+            cluster.repo.to_string(),
+            cluster.suite.clone(),
+            cluster.component.clone(),
+            cluster.severity.as_str().to_string(),
+            cluster.errors.to_string(),
+            cluster.example.clone(),
+        ]);
+    }
+    print_table(&table);
+    Ok(())
+}
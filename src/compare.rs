@@ -0,0 +1,188 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::{apt::AptRepo, config::*, table::print_table, version};
+
+// Adds the highest version of each source package in a suite to versions, returning the
+// codename from its Release, which differs from the suite for aliases like devel
+pub async fn suite_versions(
+    repo: &AptRepo,
+    suite: &str,
+    versions: &mut BTreeMap<String, String>,
+) -> Result<Option<String>> {
+    let mut codename = None;
+    for release in repo.release(suite).await? {
+        codename = release.codename.clone();
+        for component in release
+            .components
+            .as_ref()
+            .ok_or(anyhow!("release missing components"))?
+        {
+            let split = release.split_sources(component);
+            for source in repo
+                .sources(suite, component, &split, &release.files)
+                .await?
+            {
+                let (Some(package), Some(version)) = (source.package, source.version) else {
+                    continue;
+                };
+                let newer = versions.get(&package).is_none_or(|last| {
+                    deb_version::compare_versions(&version, last) == Ordering::Greater
+                });
+                if newer {
+                    versions.insert(package, version);
+                }
+            }
+        }
+    }
+    Ok(codename)
+}
+
+// Highest version of each source package in a repo for a codename, across all of its suites
+pub async fn repo_versions(
+    config: &Config,
+    repo_kind: RepoKind,
+    codename: Codename,
+) -> Result<BTreeMap<String, String>> {
+    let repo = AptRepo::new(config, repo_kind)?;
+    let mut versions = BTreeMap::<String, String>::new();
+    for suite in config.suites(repo_kind, codename) {
+        suite_versions(&repo, &suite.to_string(), &mut versions).await?;
+    }
+    Ok(versions)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Difference {
+    NewerInA,
+    NewerInB,
+    OnlyInA,
+    OnlyInB,
+}
+
+impl Difference {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::NewerInA => "newer in A",
+            Self::NewerInB => "newer in B",
+            Self::OnlyInA => "only in A",
+            Self::OnlyInB => "only in B",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Row {
+    package: String,
+    a: Option<String>,
+    b: Option<String>,
+    difference: Difference,
+}
+
+// Prints packages whose versions differ between two repos for a codename
+pub async fn compare(
+    config: &Config,
+    repo_a: RepoKind,
+    repo_b: RepoKind,
+    codename: Codename,
+    json: bool,
+    upstream_only: bool,
+) -> Result<()> {
+    let (versions_a, mut versions_b) = tokio::try_join!(
+        repo_versions(config, repo_a, codename),
+        repo_versions(config, repo_b, codename)
+    )?;
+
+    let mut rows = Vec::new();
+    for (package, a) in versions_a {
+        let b = versions_b.remove(&package);
+        let difference = match &b {
+            Some(b) => match version::compare_versions(upstream_only, &a, b) {
+                Ordering::Greater => Difference::NewerInA,
+                Ordering::Less => Difference::NewerInB,
+                Ordering::Equal => continue,
+            },
+            None => Difference::OnlyInA,
+        };
+        rows.push(Row {
+            package,
+            a: Some(a),
+            b,
+            difference,
+        });
+    }
+    for (package, b) in versions_b {
+        rows.push(Row {
+            package,
+            a: None,
+            b: Some(b),
+            difference: Difference::OnlyInB,
+        });
+    }
+    rows.sort_by(|x, y| x.package.cmp(&y.package));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    let mut table = vec![vec![
+        "Package".to_string(),
+        format!("A: {}", repo_a.as_str()),
+        format!("B: {}", repo_b.as_str()),
+        "Difference".to_string(),
+    ]];
+    for row in rows {
+        table.push(vec![
+            row.package,
+            row.a.unwrap_or_else(|| "None".to_string()),
+            row.b.unwrap_or_else(|| "None".to_string()),
+            row.difference.as_str().to_string(),
+        ]);
+    }
+    print_table(&table);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct MissingRow {
+    package: String,
+    version: String,
+}
+
+// Prints packages in repo A that are absent from repo B for a codename, regardless of version
+pub async fn missing(
+    config: &Config,
+    repo_a: RepoKind,
+    repo_b: RepoKind,
+    codename: Codename,
+    json: bool,
+) -> Result<()> {
+    let (versions_a, versions_b) = tokio::try_join!(
+        repo_versions(config, repo_a, codename),
+        repo_versions(config, repo_b, codename)
+    )?;
+
+    let rows: Vec<MissingRow> = versions_a
+        .into_iter()
+        .filter(|(package, _)| !versions_b.contains_key(package))
+        .map(|(package, version)| MissingRow { package, version })
+        .collect();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    let mut table = vec![vec![
+        "Package".to_string(),
+        format!("{} version", repo_a.as_str()),
+    ]];
+    for row in rows {
+        table.push(vec![row.package, row.version]);
+    }
+    print_table(&table);
+    Ok(())
+}
@@ -1,11 +1,18 @@
-use std::fmt;
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, fs, path::Path, path::PathBuf};
+use url::Url;
+use wildmatch::WildMatch;
 
-pub const GITHUB_ORG: &'static str = "pop-os";
+pub const GITHUB_ORG: &str = "pop-os";
+
+// Host Octocrab sends GitHub API requests to
+pub const GITHUB_API_HOST: &str = "api.github.com";
 
 // Filter for all pop-os PRs that are open and not drafts
-pub const GITHUB_PR_FILTER_BASE: &'static str =
-    "is:open is:pr archived:false draft:false user:pop-os";
-pub const GITHUB_PR_FILTERS: &'static [(&'static str, &'static str)] = &[
+pub const GITHUB_PR_FILTER_BASE: &str = "is:open is:pr archived:false draft:false user:pop-os";
+pub const GITHUB_PR_FILTERS: &[(&str, &str)] = &[
     (
         "PRs pending engineering assignment",
         "review:none -team-review-requested:pop-os/engineering",
@@ -25,7 +32,10 @@ pub const GITHUB_PR_FILTERS: &'static [(&'static str, &'static str)] = &[
     ("PRs pending merge", "review:approved"),
 ];
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum Codename {
     Jammy,
     Noble,
@@ -33,13 +43,31 @@ pub enum Codename {
 }
 
 impl Codename {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Jammy => "jammy",
             Self::Noble => "noble",
             Self::Resolute => "resolute",
         }
     }
+
+    // The codename released after this one, if any
+    pub fn next(&self) -> Option<Self> {
+        match self {
+            Self::Jammy => Some(Self::Noble),
+            Self::Noble => Some(Self::Resolute),
+            Self::Resolute => None,
+        }
+    }
+
+    // The codename released before this one, if any
+    pub fn previous(&self) -> Option<Self> {
+        match self {
+            Self::Jammy => None,
+            Self::Noble => Some(Self::Jammy),
+            Self::Resolute => Some(Self::Noble),
+        }
+    }
 }
 
 impl fmt::Display for Codename {
@@ -48,21 +76,24 @@ impl fmt::Display for Codename {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum SuiteKind {
     Standard,
     Security,
     Updates,
     Backports,
+    // Any other suffix, like -proposed
+    Custom(String),
 }
 
 impl SuiteKind {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Standard => "",
             Self::Security => "-security",
             Self::Updates => "-updates",
             Self::Backports => "-backports",
+            Self::Custom(suffix) => suffix,
         }
     }
 }
@@ -73,16 +104,23 @@ impl fmt::Display for SuiteKind {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Suite(Codename, SuiteKind);
 
+impl Suite {
+    pub fn kind(&self) -> &SuiteKind {
+        &self.1
+    }
+}
+
 impl fmt::Display for Suite {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}{}", self.0.as_str(), self.1.as_str())
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
 pub enum Arch {
     Amd64,
     Arm64,
@@ -91,7 +129,7 @@ pub enum Arch {
 }
 
 impl Arch {
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Amd64 => "amd64",
             Self::Arm64 => "arm64",
@@ -107,7 +145,11 @@ impl fmt::Display for Arch {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
 pub enum RepoKind {
     Release,
     Staging,
@@ -130,6 +172,18 @@ impl RepoKind {
         ]
     }
 
+    // Name used for this repo in config files and on the command line
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Release => "release",
+            Self::Staging => "staging",
+            Self::StagingUbuntu => "staging_ubuntu",
+            Self::Stable => "stable",
+            Self::PreStable => "pre_stable",
+            Self::Ubuntu => "ubuntu",
+        }
+    }
+
     pub fn must_be_newer_than(&self) -> Vec<Self> {
         match self {
             Self::Release => vec![Self::Ubuntu],
@@ -141,7 +195,7 @@ impl RepoKind {
         }
     }
 
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Self::Release => "Release",
             Self::Staging => "Staging",
@@ -168,18 +222,18 @@ impl RepoKind {
         &[Codename::Jammy, Codename::Noble, Codename::Resolute]
     }
 
-    pub fn suites(&self, codename: Codename) -> Vec<Suite> {
+    pub fn suite_kinds(&self) -> Vec<SuiteKind> {
         match self {
             Self::Ubuntu => {
                 vec![
-                    Suite(codename, SuiteKind::Standard),
-                    Suite(codename, SuiteKind::Security),
-                    Suite(codename, SuiteKind::Updates),
-                    Suite(codename, SuiteKind::Backports),
+                    SuiteKind::Standard,
+                    SuiteKind::Security,
+                    SuiteKind::Updates,
+                    SuiteKind::Backports,
                 ]
             }
             _ => {
-                vec![Suite(codename, SuiteKind::Standard)]
+                vec![SuiteKind::Standard]
             }
         }
     }
@@ -191,3 +245,845 @@ impl RepoKind {
         }
     }
 }
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    // Overrides the default URL of the repo
+    pub url: Option<Url>,
+    // Suite suffixes to fetch in addition to the defaults, like -proposed
+    pub extra_suites: Vec<String>,
+    // Overrides the allowed archs for specific codenames
+    pub archs: BTreeMap<Codename, Vec<Arch>>,
+    // Suites like devel that point to a codename, resolved from their Release on each run
+    pub suite_aliases: Vec<String>,
+    // Components each codename's Release must list
+    pub components: BTreeMap<Codename, Vec<String>>,
+    pub version_link: VersionLink,
+    // Hours the newest Release of the repo may be old before it is reported as stalled
+    pub max_age_hours: Option<u64>,
+    // Extra HTTP headers sent with every request to the repo, like an API key for a CDN
+    pub headers: BTreeMap<String, Secret>,
+}
+
+// A config value that may be a credential, never shown in logs or Debug output
+#[derive(Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+// What versions in the HTML report link to
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionLink {
+    // The GitHub commit for Pop repos, or Launchpad for the PPAs and Ubuntu
+    #[default]
+    Default,
+    // The .dsc of the source package in the pool of the repo
+    Dsc,
+    // The default link, with a link to the .dsc below it
+    Both,
+}
+
+impl VersionLink {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Dsc => "dsc",
+            Self::Both => "both",
+        }
+    }
+}
+
+// Background colors of cells with errors, by severity
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    pub info: String,
+    pub warning: String,
+    pub critical: String,
+    // Versions older than in the previous codename
+    pub downgrade: String,
+    // Versions below the target in the manifest
+    pub target: String,
+    // Sources in two components of a suite at different versions
+    pub conflict: String,
+    // Sources whose .dsc is missing from the pool
+    pub dangling: String,
+    // Sources without any binaries built from them
+    pub unbuilt: String,
+    // Expected packages that are missing
+    pub missing: String,
+    // Background of the environment banner
+    pub banner: String,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            info: "#404040".to_string(),
+            warning: "#806000".to_string(),
+            critical: "#800000".to_string(),
+            downgrade: "#006060".to_string(),
+            target: "#600060".to_string(),
+            conflict: "#203080".to_string(),
+            dangling: "#504020".to_string(),
+            unbuilt: "#305020".to_string(),
+            missing: "#804000".to_string(),
+            banner: "#804000".to_string(),
+        }
+    }
+}
+
+// Options of the DataTables package tables in the HTML report
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HtmlConfig {
+    // Tables with more rows than this are split into pages, as browsers struggle with tens of
+    // thousands of rows on one page
+    pub paging_threshold: usize,
+    // Rows per page of paged tables
+    pub page_length: usize,
+    // Initial sort as column index and direction, the first column being Errors
+    pub order: Vec<(usize, SortDirection)>,
+}
+
+impl Default for HtmlConfig {
+    fn default() -> Self {
+        Self {
+            paging_threshold: 2000,
+            page_length: 100,
+            order: vec![
+                (0, SortDirection::Desc),
+                (1, SortDirection::Asc),
+                (2, SortDirection::Asc),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    // Seconds to keep idle connections open for reuse
+    pub pool_idle_timeout: u64,
+    // Idle connections to keep open per host
+    pub pool_max_idle_per_host: usize,
+    // Seconds to reuse resolved addresses for, 0 uses the system resolver for every connection
+    pub dns_cache_ttl: u64,
+    // Hosts to fetch from without checking TLS certificates, only for emergencies
+    pub insecure_hosts: Vec<String>,
+    // Lowest TLS version to connect with, connections to servers without it fail
+    pub min_tls_version: TlsVersion,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tls12 => "1.2",
+            Self::Tls13 => "1.3",
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout: 90,
+            pool_max_idle_per_host: 32,
+            dns_cache_ttl: 0,
+            insecure_hosts: Vec::new(),
+            min_tls_version: TlsVersion::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // File containing the GitHub token
+    pub github_token: PathBuf,
+    // File to cache GitHub PR counts in
+    pub github_cache: PathBuf,
+    // Seconds to reuse cached GitHub PR counts for, 0 disables the cache
+    pub github_cache_ttl: u64,
+    // File to cache GitHub commit dates in for --commit-dates, kept forever as they never change
+    pub commit_date_cache: PathBuf,
+    pub repos: BTreeMap<RepoKind, RepoConfig>,
+    // Tracking issue for packages by name, which may contain * and ? wildcards
+    pub tracking: BTreeMap<String, Url>,
+    pub palette: Palette,
+    // Suite of the Ubuntu development series, checked with --ubuntu-devel
+    pub ubuntu_devel_suite: String,
+    // Ubuntu pockets, by suffix, whose versions make up the Ubuntu version compared against, or
+    // all fetched pockets if unset
+    pub ubuntu_compared_pockets: Option<Vec<String>>,
+    // Hosts that may be contacted, including by redirects, empty allows any host
+    pub allowed_hosts: Vec<String>,
+    // Sources and Packages indices to download at once, across all repos
+    pub max_concurrent_fetches: usize,
+    // Seconds over which fetches ramp up from fetch_ramp_start to max_concurrent_fetches, 0 is off
+    pub fetch_ramp_seconds: u64,
+    // Fetches allowed at once when the run starts, with the ramp on
+    pub fetch_ramp_start: usize,
+    // Transient failures to retry over the whole run, after which they fail immediately
+    pub retry_budget: u64,
+    // Largest single stanza in an index, in bytes, so a broken mirror cannot exhaust memory
+    pub max_stanza_bytes: u64,
+    // Largest decompressed index, in bytes
+    pub max_index_bytes: u64,
+    // Check the length of each index against its size in the Release
+    pub verify_index_sizes: bool,
+    pub http: HttpConfig,
+    pub html: HtmlConfig,
+    // Title of the report
+    pub title: String,
+    // Environment the report is for, like prod or staging, shown in a banner if set
+    pub environment: Option<String>,
+    // UDP address like 127.0.0.1:8125 to send error counts to as StatsD gauges after a run
+    pub statsd_address: Option<String>,
+    // Prefix of the names of StatsD gauges
+    pub statsd_prefix: String,
+    // Minimum version each package should reach for a release, by codename and package
+    pub manifest: BTreeMap<Codename, BTreeMap<String, String>>,
+    // Packages that must be in a repo for a codename, by repo and codename, reported when they
+    // are missing entirely
+    pub expected_packages: BTreeMap<RepoKind, BTreeMap<Codename, Vec<String>>>,
+    // Packages allowed to be older in a later codename, which may contain * and ? wildcards
+    pub codename_order_exempt: Vec<String>,
+    // Codename each codename is checked against by --check-codename-order, for codenames seeded
+    // from another than the one released before them
+    pub prior_codenames: BTreeMap<Codename, Codename>,
+    // Ubuntu packages listed by --carry-candidates when no Pop repo has them, by component, which
+    // may contain * and ? wildcards
+    pub carry_candidates: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            github_token: PathBuf::from(".github_token"),
+            github_cache: PathBuf::from(".github_cache.json"),
+            github_cache_ttl: 0,
+            commit_date_cache: PathBuf::from(".github_commit_dates.json"),
+            repos: BTreeMap::new(),
+            tracking: BTreeMap::new(),
+            palette: Palette::default(),
+            // Ubuntu points this at whichever series is in development
+            ubuntu_devel_suite: "devel".to_string(),
+            ubuntu_compared_pockets: None,
+            allowed_hosts: Vec::new(),
+            max_concurrent_fetches: 16,
+            fetch_ramp_seconds: 0,
+            fetch_ramp_start: 2,
+            retry_budget: 50,
+            // Real stanzas are a few KiB and the largest Ubuntu indices a few hundred MiB
+            max_stanza_bytes: 16 * 1024 * 1024,
+            max_index_bytes: 2 * 1024 * 1024 * 1024,
+            verify_index_sizes: false,
+            http: HttpConfig::default(),
+            html: HtmlConfig::default(),
+            title: "Poparazzi".to_string(),
+            environment: None,
+            statsd_address: None,
+            statsd_prefix: "poparazzi".to_string(),
+            manifest: BTreeMap::new(),
+            expected_packages: BTreeMap::new(),
+            codename_order_exempt: Vec::new(),
+            prior_codenames: BTreeMap::new(),
+            carry_candidates: BTreeMap::new(),
+        }
+    }
+}
+
+// All problems found when validating a config, with the path of the field they were found in
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config:")?;
+        for problem in self.problems.iter() {
+            write!(f, "\n    {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Recursively merges overlay into base. Tables are merged key by key, any other value
+// (including arrays) in the overlay replaces the value in base.
+fn merge_table(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_table(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// Turns an override like repos.staging.url=https://mirror.example.com/ into a table holding only
+// that key. The value is parsed as TOML, or else taken as a string, so strings need no quotes.
+fn override_table(assignment: &str) -> Result<toml::Table> {
+    let (path, raw) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow!("--set {assignment:?}: expected KEY=VALUE"))?;
+    let keys: Vec<_> = path.trim().split('.').collect();
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(anyhow!("--set {assignment:?}: invalid key {path:?}"));
+    }
+    let raw = raw.trim();
+    let mut value = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()));
+    for key in keys.iter().rev() {
+        let mut table = toml::Table::new();
+        table.insert(key.to_string(), value);
+        value = toml::Value::Table(table);
+    }
+    match value {
+        toml::Value::Table(table) => Ok(table),
+        _ => unreachable!("keys are never empty"),
+    }
+}
+
+impl Config {
+    // Loads config files in order, with later files overriding keys from earlier ones, then
+    // applies --set overrides in order on top of them
+    pub fn load<P: AsRef<Path>>(paths: &[P], overrides: &[String]) -> Result<Self> {
+        let mut table = toml::Table::new();
+        for path in paths {
+            let path = path.as_ref();
+            let data = fs::read_to_string(path)
+                .with_context(|| format!("failed to read config {}", path.display()))?;
+            let overlay: toml::Table = toml::from_str(&data)
+                .with_context(|| format!("failed to parse config {}", path.display()))?;
+            merge_table(&mut table, overlay);
+        }
+        for assignment in overrides {
+            merge_table(&mut table, override_table(assignment)?);
+        }
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .context("failed to load config")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Checks for problems that parsing alone does not catch, reporting all of them at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if self.github_token.as_os_str().is_empty() {
+            problems.push("github_token: must not be empty".to_string());
+        }
+        if self.max_concurrent_fetches == 0 {
+            problems.push("max_concurrent_fetches: must be at least 1".to_string());
+        }
+        if self.fetch_ramp_seconds > 0
+            && !(1..=self.max_concurrent_fetches).contains(&self.fetch_ramp_start)
+        {
+            problems.push(format!(
+                "fetch_ramp_start: must be between 1 and max_concurrent_fetches ({})",
+                self.max_concurrent_fetches
+            ));
+        }
+        if self.html.page_length == 0 {
+            problems.push("html.page_length: must be at least 1".to_string());
+        }
+        if self.max_stanza_bytes == 0 {
+            problems.push("max_stanza_bytes: must be at least 1".to_string());
+        }
+        if self.max_index_bytes < self.max_stanza_bytes {
+            problems.push("max_index_bytes: must be at least max_stanza_bytes".to_string());
+        }
+        if self.title.trim().is_empty() {
+            problems.push("title: must not be empty".to_string());
+        }
+        if self.ubuntu_devel_suite.is_empty() || self.ubuntu_devel_suite.contains('/') {
+            problems.push("ubuntu_devel_suite: must be a suite name like devel".to_string());
+        }
+        if let Some(pockets) = &self.ubuntu_compared_pockets {
+            if pockets.is_empty() {
+                problems.push("ubuntu_compared_pockets: must not be empty".to_string());
+            }
+            let fetched: Vec<_> = self
+                .suite_kinds(RepoKind::Ubuntu)
+                .iter()
+                .map(|kind| kind.as_str().to_string())
+                .chain(self.suite_aliases(RepoKind::Ubuntu).iter().cloned())
+                .collect();
+            for pocket in pockets {
+                if !fetched.contains(pocket) {
+                    problems.push(format!(
+                        "ubuntu_compared_pockets: {pocket:?} is not a fetched Ubuntu suite, one of {fetched:?}"
+                    ));
+                }
+            }
+        }
+        if let Some(address) = &self.statsd_address {
+            let port = address.rsplit_once(':').map(|(_host, port)| port);
+            if port.is_none_or(|port| port.parse::<u16>().is_err()) {
+                problems.push(format!(
+                    "statsd_address: {address:?} must be a host and port like 127.0.0.1:8125"
+                ));
+            }
+        }
+        // Gauge names are written into StatsD lines, where : and | separate fields
+        if self.statsd_prefix.is_empty()
+            || !self
+                .statsd_prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        {
+            problems.push(format!(
+                "statsd_prefix: {:?} must only have letters, digits, ., _, and -",
+                self.statsd_prefix
+            ));
+        }
+        if self.commit_date_cache.as_os_str().is_empty() {
+            problems.push("commit_date_cache: must not be empty".to_string());
+        }
+        if self.github_cache_ttl > 0 && self.github_cache.as_os_str().is_empty() {
+            problems
+                .push("github_cache: must not be empty when github_cache_ttl is set".to_string());
+        }
+        for (repo_kind, repo) in self.repos.iter() {
+            let Some(url) = &repo.url else {
+                continue;
+            };
+            let field = format!("repos.{}.url", repo_kind.id());
+            if !matches!(url.scheme(), "http" | "https") {
+                problems.push(format!(
+                    "{field}: unsupported scheme {:?}, expected http or https",
+                    url.scheme()
+                ));
+            }
+            if url.cannot_be_a_base() {
+                problems.push(format!("{field}: {url} cannot be used as a base URL"));
+            }
+            if url.query().is_some() || url.fragment().is_some() {
+                problems.push(format!("{field}: {url} must not have a query or fragment"));
+            }
+        }
+        // Certificate checks may only be turned off host by host, never for everything
+        for host in self.http.insecure_hosts.iter() {
+            if host.is_empty() || host.contains(['/', ':', '*', '?']) {
+                problems.push(format!(
+                    "http.insecure_hosts: {host:?} must be a host name without a scheme, port, or wildcards"
+                ));
+            }
+        }
+        for host in self.allowed_hosts.iter() {
+            if host.is_empty() || host.contains(['/', ':']) {
+                problems.push(format!(
+                    "allowed_hosts: {host:?} must be a host name without a scheme or port"
+                ));
+            }
+        }
+        if !self.allowed_hosts.is_empty() {
+            if let Some(host) = self.statsd_host()
+                && !crate::apt::host_allowed(&self.allowed_hosts, Some(host))
+            {
+                problems.push(format!(
+                    "allowed_hosts: does not include {host} used by statsd_address"
+                ));
+            }
+            for repo_kind in RepoKind::all() {
+                let url = self.repo_url(repo_kind);
+                if !crate::apt::host_allowed(&self.allowed_hosts, url.host_str()) {
+                    problems.push(format!(
+                        "allowed_hosts: does not include the host of {url} used by repos.{}",
+                        repo_kind.id()
+                    ));
+                }
+            }
+        }
+        for (repo_kind, repo) in self.repos.iter() {
+            for (name, value) in repo.headers.iter() {
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    problems.push(format!(
+                        "repos.{}.headers: {name:?} is not a valid header name",
+                        repo_kind.id()
+                    ));
+                }
+                // The value is left out, as it may be a credential
+                if reqwest::header::HeaderValue::from_str(value.expose()).is_err() {
+                    problems.push(format!(
+                        "repos.{}.headers: the value of {name:?} is not a valid header value",
+                        repo_kind.id()
+                    ));
+                }
+            }
+            for alias in repo.suite_aliases.iter() {
+                let valid = !alias.is_empty()
+                    && alias
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+                if !valid {
+                    problems.push(format!(
+                        "repos.{}.suite_aliases: {alias:?} must be a suite name like \"devel\"",
+                        repo_kind.id()
+                    ));
+                }
+            }
+            for suffix in repo.extra_suites.iter() {
+                let valid = suffix.len() > 1
+                    && suffix.starts_with('-')
+                    && suffix[1..]
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+                if !valid {
+                    problems.push(format!(
+                        "repos.{}.extra_suites: {suffix:?} must be a suffix like \"-proposed\"",
+                        repo_kind.id()
+                    ));
+                }
+            }
+        }
+        for (repo_kind, codenames) in self.expected_packages.iter() {
+            for (codename, packages) in codenames.iter() {
+                if packages.iter().any(|package| package.is_empty()) {
+                    problems.push(format!(
+                        "expected_packages.{}.{codename}: package names must not be empty",
+                        repo_kind.id()
+                    ));
+                }
+            }
+        }
+        for (codename, prior) in self.prior_codenames.iter() {
+            if prior >= codename {
+                problems.push(format!(
+                    "prior_codenames.{codename}: {prior} must be released before {codename}"
+                ));
+            }
+        }
+        for (codename, targets) in self.manifest.iter() {
+            for (package, version) in targets.iter() {
+                if version.is_empty() || version.contains(char::is_whitespace) {
+                    problems.push(format!(
+                        "manifest.{}.{package}: {version:?} must be a version",
+                        codename.as_str()
+                    ));
+                }
+            }
+        }
+        for (package, url) in self.tracking.iter() {
+            if !matches!(url.scheme(), "http" | "https") {
+                problems.push(format!(
+                    "tracking.{package}: unsupported scheme {:?}, expected http or https",
+                    url.scheme()
+                ));
+            }
+        }
+        for (name, color) in [
+            ("info", &self.palette.info),
+            ("warning", &self.palette.warning),
+            ("critical", &self.palette.critical),
+            ("downgrade", &self.palette.downgrade),
+            ("target", &self.palette.target),
+            ("conflict", &self.palette.conflict),
+            ("dangling", &self.palette.dangling),
+            ("unbuilt", &self.palette.unbuilt),
+            ("missing", &self.palette.missing),
+            ("banner", &self.palette.banner),
+        ] {
+            // Colors are written into CSS, so only allow names and hex colors
+            let valid = !color.is_empty()
+                && color
+                    .strip_prefix('#')
+                    .unwrap_or(color)
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric());
+            if !valid {
+                problems.push(format!(
+                    "palette.{name}: {color:?} must be a color name or hex color"
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    // Tracking issue for a package, preferring an exact match over wildcards
+    pub fn tracking_url(&self, package: &str) -> Option<&Url> {
+        self.tracking.get(package).or_else(|| {
+            self.tracking
+                .iter()
+                .find(|(pattern, _)| WildMatch::new(pattern).matches(package))
+                .map(|(_, url)| url)
+        })
+    }
+
+    // Whether a package is exempt from --check-codename-order
+    pub fn codename_order_exempt(&self, package: &str) -> bool {
+        self.codename_order_exempt
+            .iter()
+            .any(|pattern| WildMatch::new(pattern).matches(package))
+    }
+
+    // Codename whose versions a codename must not be older than with --check-codename-order
+    pub fn prior_codename(&self, codename: Codename) -> Option<Codename> {
+        match self.prior_codenames.get(&codename) {
+            Some(prior) => Some(*prior),
+            None => codename.previous(),
+        }
+    }
+
+    // Whether an Ubuntu package in a component is listed by --carry-candidates
+    pub fn carry_candidate(&self, component: &str, package: &str) -> bool {
+        self.carry_candidates
+            .get(component)
+            .is_some_and(|patterns| {
+                patterns
+                    .iter()
+                    .any(|pattern| WildMatch::new(pattern).matches(package))
+            })
+    }
+
+    // Target version of a package for a codename from the manifest, if any
+    pub fn target_version(&self, package: &str, codename: Codename) -> Option<&str> {
+        self.manifest
+            .get(&codename)?
+            .get(package)
+            .map(|version| version.as_str())
+    }
+
+    // Host of statsd_address, without the brackets of an IPv6 address
+    pub fn statsd_host(&self) -> Option<&str> {
+        let (host, _port) = self.statsd_address.as_ref()?.rsplit_once(':')?;
+        Some(host.trim_start_matches('[').trim_end_matches(']'))
+    }
+
+    // Repos each expected package must be in, by package and codename
+    pub fn expected_packages(&self) -> BTreeMap<(&str, Codename), Vec<RepoKind>> {
+        let mut expected = BTreeMap::<_, Vec<_>>::new();
+        for (repo_kind, codenames) in self.expected_packages.iter() {
+            for (codename, packages) in codenames.iter() {
+                for package in packages.iter() {
+                    expected
+                        .entry((package.as_str(), *codename))
+                        .or_default()
+                        .push(*repo_kind);
+                }
+            }
+        }
+        expected
+    }
+
+    // Title with the environment, to tell reports for different environments apart
+    pub fn report_title(&self) -> String {
+        match &self.environment {
+            Some(environment) => format!("{} ({environment})", self.title),
+            None => self.title.clone(),
+        }
+    }
+
+    // Default suite kinds of a repo followed by any configured extra suites
+    pub fn suite_kinds(&self, repo_kind: RepoKind) -> Vec<SuiteKind> {
+        let mut kinds = repo_kind.suite_kinds();
+        if let Some(repo) = self.repos.get(&repo_kind) {
+            for suffix in repo.extra_suites.iter() {
+                kinds.push(SuiteKind::Custom(suffix.clone()));
+            }
+        }
+        kinds
+    }
+
+    // Whether a suite suffix of a Pop repo is one of its extra_suites, whose versions are kept
+    // apart from the version users get. Ubuntu treats them as pockets like any other.
+    pub fn pop_extra_suite(&self, repo_kind: RepoKind, pocket: &str) -> bool {
+        repo_kind != RepoKind::Ubuntu
+            && self
+                .repos
+                .get(&repo_kind)
+                .is_some_and(|repo| repo.extra_suites.iter().any(|suffix| suffix == pocket))
+    }
+
+    pub fn expected_components(&self, repo_kind: RepoKind, codename: Codename) -> &[String] {
+        self.repos
+            .get(&repo_kind)
+            .and_then(|repo| repo.components.get(&codename))
+            .map_or(&[], |components| components.as_slice())
+    }
+
+    pub fn max_age_hours(&self, repo_kind: RepoKind) -> Option<u64> {
+        self.repos.get(&repo_kind)?.max_age_hours
+    }
+
+    // Whether TLS certificates of a host are not checked, as it is in http.insecure_hosts
+    pub fn insecure_host(&self, host: Option<&str>) -> bool {
+        host.is_some_and(|host| {
+            self.http
+                .insecure_hosts
+                .iter()
+                .any(|insecure| insecure.eq_ignore_ascii_case(host))
+        })
+    }
+
+    pub fn headers(&self, repo_kind: RepoKind) -> Option<&BTreeMap<String, Secret>> {
+        self.repos.get(&repo_kind).map(|repo| &repo.headers)
+    }
+
+    pub fn version_link(&self, repo_kind: RepoKind) -> VersionLink {
+        self.repos
+            .get(&repo_kind)
+            .map_or(VersionLink::Default, |repo| repo.version_link)
+    }
+
+    // Whether versions from an Ubuntu pocket, given as its suffix or alias, feed the Ubuntu version
+    pub fn ubuntu_pocket_compared(&self, pocket: &str) -> bool {
+        self.ubuntu_compared_pockets
+            .as_ref()
+            .is_none_or(|pockets| pockets.iter().any(|compared| compared == pocket))
+    }
+
+    pub fn suite_aliases(&self, repo_kind: RepoKind) -> &[String] {
+        self.repos
+            .get(&repo_kind)
+            .map_or(&[], |repo| repo.suite_aliases.as_slice())
+    }
+
+    pub fn suites(&self, repo_kind: RepoKind, codename: Codename) -> Vec<Suite> {
+        self.suite_kinds(repo_kind)
+            .into_iter()
+            .map(|kind| Suite(codename, kind))
+            .collect()
+    }
+
+    // Archs to check for a repo and codename, which can be narrowed as archs are phased out
+    pub fn allowed_archs(&self, repo_kind: RepoKind, codename: Codename) -> &[Arch] {
+        self.repos
+            .get(&repo_kind)
+            .and_then(|repo| repo.archs.get(&codename))
+            .map_or(repo_kind.allowed_archs(), |archs| archs.as_slice())
+    }
+
+    // URL of a repo, always ending in a slash so that paths are joined below it. This keeps
+    // URLs with a path, like a snapshot at https://snapshot.example.com/ubuntu/20250101T000000Z,
+    // from losing their last segment.
+    pub fn repo_url(&self, repo_kind: RepoKind) -> Url {
+        let mut url = self
+            .repos
+            .get(&repo_kind)
+            .and_then(|repo| repo.url.clone())
+            .unwrap_or_else(|| repo_kind.url());
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Loads a config the way --config and --set do, from a file written for each call
+    fn load(config: &str, overrides: &[&str]) -> Result<Config> {
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "poparazzi-test-{}-{}.toml",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, config).unwrap();
+        let overrides: Vec<_> = overrides.iter().map(|x| x.to_string()).collect();
+        let config = Config::load(&[&path], &overrides);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    fn problems(config: &str, overrides: &[&str]) -> Vec<String> {
+        let err = load(config, overrides).unwrap_err();
+        err.downcast::<ConfigError>().unwrap().problems
+    }
+
+    #[test]
+    fn unknown_repo_is_rejected() {
+        let err = load("[repos.nightly]\nurl = \"https://apt.example.com/\"\n", &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("nightly"), "{err:#}");
+    }
+
+    #[test]
+    fn unknown_codename_is_rejected() {
+        let err = load("[prior_codenames]\noracular = \"noble\"\n", &[]).unwrap_err();
+        assert!(format!("{err:#}").contains("oracular"), "{err:#}");
+    }
+
+    #[test]
+    fn all_problems_are_reported_together() {
+        let config = r#"
+max_concurrent_fetches = 0
+
+[repos.staging]
+url = "ftp://apt.example.com/staging"
+suite_aliases = ["Devel"]
+
+[palette]
+info = "red;"
+"#;
+        assert_eq!(
+            problems(config, &["prior_codenames.noble=resolute"]),
+            [
+                "max_concurrent_fetches: must be at least 1",
+                "repos.staging.url: unsupported scheme \"ftp\", expected http or https",
+                "repos.staging.suite_aliases: \"Devel\" must be a suite name like \"devel\"",
+                "prior_codenames.noble: resolute must be released before noble",
+                "palette.info: \"red;\" must be a color name or hex color",
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicting_overrides_are_checked_after_merging() {
+        let config = "max_stanza_bytes = 100\nmax_index_bytes = 1000\n";
+        assert_eq!(
+            problems(config, &["max_index_bytes=10", "title= "]),
+            [
+                "max_index_bytes: must be at least max_stanza_bytes",
+                "title: must not be empty",
+            ]
+        );
+        let config = load(config, &["max_index_bytes=10", "max_index_bytes=200"]).unwrap();
+        assert_eq!(config.max_index_bytes, 200);
+    }
+}
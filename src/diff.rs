@@ -0,0 +1,87 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::{Args, config::*, load_apt_infos, model, table::print_table};
+
+#[derive(Serialize)]
+struct Row<'a> {
+    package: &'a str,
+    codename: Codename,
+    repo: &'static str,
+    local: Option<&'a str>,
+    other: Option<&'a str>,
+}
+
+// Prints versions that differ between the packages computed here and a model saved by another
+// instance. Versions are compared as strings, as two mirrors of the same repo should publish the
+// very same versions.
+pub async fn diff(args: &Args, config: &Config, other: &str, json: bool) -> Result<()> {
+    let local = load_apt_infos(args, config).await?;
+    let remote = model::load_location(config, other).await?;
+    for (side, fetched) in [("local", &local), ("other", &remote)] {
+        if !fetched.failed_fetches.is_empty() {
+            log::warn!(
+                "{side} model has {} failed fetches, their packages will show as differing",
+                fetched.failed_fetches.len()
+            );
+        }
+    }
+
+    let keys: BTreeSet<_> = local
+        .apt_infos
+        .keys()
+        .chain(remote.apt_infos.keys())
+        .collect();
+    let mut rows = Vec::new();
+    for key in keys {
+        let (package, codename) = key;
+        let (local_info, remote_info) = (local.apt_infos.get(key), remote.apt_infos.get(key));
+        for repo_kind in RepoKind::all() {
+            let local_version = local_info
+                .and_then(|apt_info| apt_info.version(repo_kind).as_ref())
+                .map(|version| version.version.as_str());
+            let remote_version = remote_info
+                .and_then(|apt_info| apt_info.version(repo_kind).as_ref())
+                .map(|version| version.version.as_str());
+            if local_version == remote_version {
+                continue;
+            }
+            rows.push(Row {
+                package,
+                codename: *codename,
+                repo: repo_kind.id(),
+                local: local_version,
+                other: remote_version,
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    if rows.is_empty() {
+        println!("No differences from {other}");
+        return Ok(());
+    }
+
+    let mut table = vec![vec![
+        "Package".to_string(),
+        "Codename".to_string(),
+        "Repo".to_string(),
+        "Local".to_string(),
+        "Other".to_string(),
+    ]];
+    for row in rows {
+        table.push(vec![
+            row.package.to_string(),
+            row.codename.as_str().to_string(),
+            row.repo.to_string(),
+            row.local.unwrap_or("None").to_string(),
+            row.other.unwrap_or("None").to_string(),
+        ]);
+    }
+    print_table(&table);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+
+use crate::{Args, config::*, load_apt_infos, version};
+
+// Prints how the errors of a package for a codename were found, step by step
+pub async fn explain(
+    args: &Args,
+    config: &Config,
+    package: &str,
+    codename: Codename,
+) -> Result<()> {
+    let apt_infos = load_apt_infos(args, config).await?.apt_infos;
+    let Some(apt_info) = apt_infos.get(&(package.to_string(), codename)) else {
+        println!(
+            "{package} was not found in any Pop repo for {}, Ubuntu versions are only kept for packages Pop has",
+            codename.as_str()
+        );
+        return Ok(());
+    };
+
+    println!("{package} {}", codename.as_str());
+    println!();
+    println!("Versions:");
+    for repo_kind in RepoKind::all() {
+        match apt_info.version(repo_kind) {
+            Some(version) => println!("  {}: {}", repo_kind.as_str(), version.version),
+            None if apt_info.missing.contains(&repo_kind) => {
+                println!("  {}: none, but expected", repo_kind.as_str())
+            }
+            None => println!("  {}: none", repo_kind.as_str()),
+        }
+    }
+
+    // Only known with --with-binaries, and only for binaries whose stanza has a Filename
+    let mut debs = Vec::new();
+    for repo_kind in RepoKind::all() {
+        let Some(version) = apt_info.version(repo_kind) else {
+            continue;
+        };
+        for (arch, urls) in version.deb_urls(config) {
+            for url in urls {
+                debs.push(format!("  {} {arch}: {url}", repo_kind.as_str()));
+            }
+        }
+    }
+    if !debs.is_empty() {
+        println!();
+        println!("Binaries:");
+        for deb in debs {
+            println!("{deb}");
+        }
+    }
+
+    println!();
+    println!("Checks:");
+    for repo_kind in RepoKind::all() {
+        for older_kind in repo_kind.must_be_newer_than() {
+            print!(
+                "  {} must be newer than {}: ",
+                repo_kind.as_str(),
+                older_kind.as_str()
+            );
+            let (version, older_version) =
+                (apt_info.version(repo_kind), apt_info.version(older_kind));
+            match (version, older_version) {
+                (_, None) => println!("not in {}, nothing to compare", older_kind.as_str()),
+                (Some(version), Some(older_version)) => {
+                    match version::compare_versions(
+                        args.upstream_only,
+                        &version.version,
+                        &older_version.version,
+                    ) {
+                        Ordering::Less => {
+                            let severity = match older_kind {
+                                RepoKind::Ubuntu => "info",
+                                _ => "critical",
+                            };
+                            println!(
+                                "{} is older than {}, {severity} error on {}",
+                                version.version,
+                                older_version.version,
+                                repo_kind.as_str()
+                            );
+                        }
+                        _ => println!(
+                            "{} is not older than {}, ok",
+                            version.version, older_version.version
+                        ),
+                    }
+                }
+                (None, Some(older_version)) => match older_kind {
+                    RepoKind::Ubuntu => println!(
+                        "not in {}, Ubuntu versions are not expected to be carried",
+                        repo_kind.as_str()
+                    ),
+                    _ => println!(
+                        "not in {}, warning on {} {} as it has not been promoted",
+                        repo_kind.as_str(),
+                        older_kind.as_str(),
+                        older_version.version
+                    ),
+                },
+            }
+        }
+    }
+    if let Some(target) = config.target_version(package, codename) {
+        println!("  Pop repos must reach the manifest target {target}");
+    }
+    for repo_kind in apt_info.missing.iter() {
+        println!(
+            "  {package} is an expected package and missing from {}, critical error",
+            repo_kind.as_str()
+        );
+    }
+
+    println!();
+    println!("Errors ({}):", apt_info.error_count());
+    for repo_kind in RepoKind::all() {
+        let Some(version) = apt_info.version(repo_kind) else {
+            continue;
+        };
+        for error in version.errors.borrow().iter() {
+            println!(
+                "  {} [{}]: {}",
+                repo_kind.as_str(),
+                error.severity.as_str(),
+                error.message
+            );
+        }
+    }
+    for repo_kind in apt_info.missing.iter() {
+        println!("  {} [critical]: Expected but missing", repo_kind.as_str());
+    }
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use anyhow::{Context, Result, anyhow};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use crate::config::*;
+
+// Full search query for one of GITHUB_PR_FILTERS
+pub fn pr_query(filter: &str) -> String {
+    format!("{GITHUB_PR_FILTER_BASE} {filter}")
+}
+
+// Link to the search results on GitHub
+pub fn pr_url(filter: &str) -> String {
+    format!(
+        "https://github.com/pulls?q={}",
+        urlencoding::encode(&pr_query(filter))
+    )
+}
+
+// Searches to run at once, low enough to stay clear of GitHub's secondary rate limits
+const PR_COUNT_CONCURRENCY: usize = 4;
+
+// Returns the number of PRs matching each of GITHUB_PR_FILTERS, in order
+pub async fn pr_counts(octocrab: &Octocrab) -> Result<Vec<u64>> {
+    // buffered keeps results in the order of the filters even if they finish out of order
+    stream::iter(GITHUB_PR_FILTERS)
+        .map(|(_name, filter)| async move {
+            let page = octocrab
+                .search()
+                .issues_and_pull_requests(&pr_query(filter))
+                .send()
+                .await?;
+            /*TODO: parse PR info?
+            let stream = page
+                .into_stream(&octocrab);
+            pin!(stream);
+            while let Some(pr) = stream.try_next().await? {
+                println!(" - {}: {}", pr.html_url, pr.title);
+            }
+            */
+            Ok(page.total_count.unwrap_or(0))
+        })
+        .buffered(PR_COUNT_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+// PRs fetched per filter for PrDetails, the most a GraphQL search returns at once
+pub const PR_DETAILS_LIMIT: u64 = 100;
+
+// Details of the PRs matching a filter, only fetched with GraphQL. Review decisions and teams are
+// counted over the oldest PR_DETAILS_LIMIT PRs.
+#[derive(Debug, Default, PartialEq)]
+pub struct PrDetails {
+    // Unix timestamp of when the oldest PR was opened
+    pub oldest: Option<i64>,
+    // PRs by review decision, like APPROVED or CHANGES_REQUESTED
+    pub decisions: BTreeMap<String, u64>,
+    // PRs by team whose review is requested, like engineering
+    pub teams: BTreeMap<String, u64>,
+}
+
+// Reads the PR nodes of a search, which come oldest first
+fn pr_details(nodes: &[Value]) -> PrDetails {
+    let mut details = PrDetails::default();
+    for node in nodes {
+        let created = node
+            .get("createdAt")
+            .and_then(Value::as_str)
+            .and_then(|created| chrono::DateTime::parse_from_rfc3339(created).ok())
+            .map(|created| created.timestamp());
+        if let Some(created) = created {
+            details.oldest = Some(details.oldest.map_or(created, |oldest| oldest.min(created)));
+        }
+        if let Some(decision) = node.get("reviewDecision").and_then(Value::as_str) {
+            *details.decisions.entry(decision.to_string()).or_default() += 1;
+        }
+        let requests = node
+            .pointer("/reviewRequests/nodes")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice);
+        // Users requested for review have no slug and are left out
+        let teams: BTreeSet<_> = requests
+            .iter()
+            .filter_map(|request| request.pointer("/requestedReviewer/slug")?.as_str())
+            .collect();
+        for team in teams {
+            *details.teams.entry(team.to_string()).or_default() += 1;
+        }
+    }
+    details
+}
+
+// Same as pr_counts, but using a single GraphQL request with one aliased search per filter,
+// which also returns the details of the PRs
+pub async fn pr_counts_graphql(octocrab: &Octocrab) -> Result<Vec<(u64, PrDetails)>> {
+    let mut params = Vec::new();
+    let mut searches = Vec::new();
+    let mut variables = Map::new();
+    for (i, (_name, filter)) in GITHUB_PR_FILTERS.iter().enumerate() {
+        params.push(format!("$q{i}: String!"));
+        searches.push(format!(
+            "f{i}: search(query: $q{i}, type: ISSUE, first: {PR_DETAILS_LIMIT}) {{ issueCount nodes {{ ... on PullRequest {{ createdAt reviewDecision reviewRequests(first: 20) {{ nodes {{ requestedReviewer {{ ... on Team {{ slug }} }} }} }} }} }} }}"
+        ));
+        // Sorting does not change the count, and puts the oldest PRs in the details
+        variables.insert(
+            format!("q{i}"),
+            Value::String(format!("{} sort:created-asc", pr_query(filter))),
+        );
+    }
+    let query = format!("query({}) {{ {} }}", params.join(", "), searches.join(" "));
+
+    let response: Value = octocrab
+        .graphql(&json!({ "query": query, "variables": variables }))
+        .await?;
+    // GraphQL reports query errors in the response body instead of the HTTP status
+    if let Some(errors) = response.get("errors") {
+        return Err(anyhow!("GitHub GraphQL query failed: {errors}"));
+    }
+    let data = response
+        .get("data")
+        .ok_or(anyhow!("GitHub GraphQL response missing data"))?;
+    (0..GITHUB_PR_FILTERS.len())
+        .map(|i| {
+            let search = data.get(format!("f{i}"));
+            let count = search
+                .and_then(|search| search.get("issueCount"))
+                .and_then(Value::as_u64)
+                .ok_or(anyhow!("GitHub GraphQL response missing count {i}"))?;
+            let nodes = search
+                .and_then(|search| search.get("nodes"))
+                .and_then(Value::as_array)
+                .map_or(&[][..], Vec::as_slice);
+            Ok((count, pr_details(nodes)))
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Serialize)]
+struct PrCountCache {
+    // Unix timestamp of when the counts were fetched
+    timestamp: i64,
+    // Search query and count for each filter
+    counts: Vec<(String, u64)>,
+}
+
+impl PrCountCache {
+    // Counts in the order of GITHUB_PR_FILTERS, if they were fetched with the current filters
+    fn counts(self) -> Option<Vec<u64>> {
+        if self.counts.len() != GITHUB_PR_FILTERS.len() {
+            return None;
+        }
+        let mut counts = Vec::new();
+        for ((query, count), (_name, filter)) in self.counts.into_iter().zip(GITHUB_PR_FILTERS) {
+            if query != pr_query(filter) {
+                return None;
+            }
+            counts.push(count);
+        }
+        Some(counts)
+    }
+}
+
+// Returns counts from the cache if they are younger than ttl seconds and were fetched with the
+// current filters
+pub fn cached_pr_counts(path: &Path, ttl: u64) -> Option<Vec<u64>> {
+    let data = fs::read_to_string(path).ok()?;
+    let cache: PrCountCache = match serde_json::from_str(&data) {
+        Ok(ok) => ok,
+        Err(err) => {
+            log::warn!("ignoring invalid GitHub cache {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let age = chrono::Utc::now().timestamp() - cache.timestamp;
+    if age < 0 || age as u64 >= ttl {
+        return None;
+    }
+    let counts = cache.counts()?;
+    log::info!("using GitHub PR counts cached {age} seconds ago");
+    Some(counts)
+}
+
+// Returns the counts in a GitHub cache file saved by an earlier run, however old, along with the
+// Unix timestamp of when they were fetched
+pub fn snapshot_pr_counts(path: &Path) -> Result<(i64, Vec<u64>)> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("failed to read GitHub snapshot {}", path.display()))?;
+    let cache: PrCountCache = serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse GitHub snapshot {}", path.display()))?;
+    let timestamp = cache.timestamp;
+    let counts = cache.counts().ok_or_else(|| {
+        anyhow!(
+            "GitHub snapshot {} was saved with different PR filters",
+            path.display()
+        )
+    })?;
+    Ok((timestamp, counts))
+}
+
+pub fn save_pr_counts(path: &Path, counts: &[u64]) -> Result<()> {
+    let cache = PrCountCache {
+        timestamp: chrono::Utc::now().timestamp(),
+        counts: GITHUB_PR_FILTERS
+            .iter()
+            .zip(counts)
+            .map(|((_name, filter), count)| (pr_query(filter), *count))
+            .collect(),
+    };
+    fs::write(path, serde_json::to_string(&cache)?)
+        .with_context(|| format!("failed to write GitHub cache {}", path.display()))
+}
+
+// Commit lookups at once, and at most per run, so a first run with an empty cache cannot use up
+// the API rate limit. Later runs look up the rest, as found dates are cached.
+const COMMIT_DATE_CONCURRENCY: usize = 4;
+const COMMIT_DATE_LOOKUPS_PER_RUN: usize = 200;
+
+// Dates of commits by repo and SHA as Unix timestamps, None for commits GitHub does not have
+#[derive(Default, Deserialize, Serialize)]
+pub struct CommitDates(BTreeMap<String, Option<i64>>);
+
+impl CommitDates {
+    fn key(repo: &str, commit: &str) -> String {
+        format!("{repo}/{commit}")
+    }
+
+    pub fn contains(&self, repo: &str, commit: &str) -> bool {
+        self.0.contains_key(&Self::key(repo, commit))
+    }
+
+    pub fn get(&self, repo: &str, commit: &str) -> Option<i64> {
+        self.0.get(&Self::key(repo, commit)).copied().flatten()
+    }
+}
+
+// Returns cached commit dates, or none if the cache is missing or invalid
+pub fn load_commit_dates(path: &Path) -> CommitDates {
+    let Ok(data) = fs::read_to_string(path) else {
+        return CommitDates::default();
+    };
+    serde_json::from_str(&data).unwrap_or_else(|err| {
+        log::warn!(
+            "ignoring invalid commit date cache {}: {}",
+            path.display(),
+            err
+        );
+        CommitDates::default()
+    })
+}
+
+pub fn save_commit_dates(path: &Path, dates: &CommitDates) -> Result<()> {
+    fs::write(path, serde_json::to_string(dates)?)
+        .with_context(|| format!("failed to write commit date cache {}", path.display()))
+}
+
+// Looks up the dates of commits that are not cached yet, up to COMMIT_DATE_LOOKUPS_PER_RUN.
+// Failures are logged and not cached, so they are tried again on the next run.
+pub async fn lookup_commit_dates(
+    octocrab: &Octocrab,
+    dates: &mut CommitDates,
+    commits: BTreeSet<(String, String)>,
+) {
+    let missing: Vec<_> = commits
+        .into_iter()
+        .filter(|(repo, commit)| !dates.contains(repo, commit))
+        .collect();
+    if missing.len() > COMMIT_DATE_LOOKUPS_PER_RUN {
+        log::warn!(
+            "{} commit dates are not cached, looking up {COMMIT_DATE_LOOKUPS_PER_RUN} this run",
+            missing.len()
+        );
+    }
+    let results: Vec<_> = stream::iter(missing.into_iter().take(COMMIT_DATE_LOOKUPS_PER_RUN))
+        .map(|(repo, commit)| async move {
+            let result = octocrab.commits(GITHUB_ORG, &repo).get(&commit).await;
+            (repo, commit, result)
+        })
+        .buffer_unordered(COMMIT_DATE_CONCURRENCY)
+        .collect()
+        .await;
+    let mut failed = 0;
+    for (repo, commit, result) in results {
+        let date = match result {
+            Ok(repo_commit) => repo_commit
+                .commit
+                .committer
+                .and_then(|committer| committer.date)
+                .map(|date| date.timestamp()),
+            // Commits that were force pushed away or repos that were renamed stay unknown
+            Err(octocrab::Error::GitHub { source, .. })
+                if matches!(source.status_code.as_u16(), 404 | 422) =>
+            {
+                None
+            }
+            Err(err) => {
+                log::debug!("failed to look up {repo} commit {commit}: {err}");
+                failed += 1;
+                continue;
+            }
+        };
+        dates.0.insert(CommitDates::key(&repo, &commit), date);
+    }
+    if failed > 0 {
+        log::warn!("failed to look up {failed} commit dates, they will be retried next run");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pr_details_counts_decisions_and_teams() {
+        let nodes = json!([
+            {
+                "createdAt": "2026-01-02T00:00:00Z",
+                "reviewDecision": "APPROVED",
+                "reviewRequests": {"nodes": [
+                    {"requestedReviewer": {"slug": "engineering"}},
+                    {"requestedReviewer": {"slug": "qa"}},
+                    {"requestedReviewer": {}}
+                ]}
+            },
+            {
+                "createdAt": "2026-01-01T00:00:00Z",
+                "reviewDecision": null,
+                "reviewRequests": {"nodes": [{"requestedReviewer": {"slug": "qa"}}]}
+            },
+            {}
+        ]);
+        let details = pr_details(nodes.as_array().unwrap());
+        assert_eq!(
+            details,
+            PrDetails {
+                oldest: Some(1767225600),
+                decisions: BTreeMap::from([("APPROVED".to_string(), 1)]),
+                teams: BTreeMap::from([("engineering".to_string(), 1), ("qa".to_string(), 2)]),
+            }
+        );
+    }
+
+    #[test]
+    fn pr_details_of_no_prs_is_empty() {
+        assert_eq!(pr_details(&[]), PrDetails::default());
+    }
+}
@@ -0,0 +1,26 @@
+use crate::config::*;
+
+// Prints the must_be_newer_than relationships between repos as a Graphviz DOT graph, with an
+// edge from each repo to the repos it must be newer than
+pub fn graph() {
+    println!("digraph repos {{");
+    println!("    rankdir=LR;");
+    for repo_kind in RepoKind::all() {
+        println!("    {} [label={:?}];", repo_kind.id(), repo_kind.as_str());
+    }
+    for repo_kind in RepoKind::all() {
+        for older_kind in repo_kind.must_be_newer_than() {
+            // Being behind Ubuntu is only informational, so draw those edges differently
+            let style = match older_kind {
+                RepoKind::Ubuntu => "dashed",
+                _ => "solid",
+            };
+            println!(
+                "    {} -> {} [label=\"newer than\", style={style}];",
+                repo_kind.id(),
+                older_kind.id()
+            );
+        }
+    }
+    println!("}}");
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{collections::BTreeMap, io::Write};
+
+use crate::{AptInfos, config::*};
+
+#[derive(Serialize)]
+struct ErrorRecord {
+    severity: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct VersionRecord<'a> {
+    version: &'a str,
+    // Only with --all-versions
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    superseded: &'a [String],
+    // Highest binary version by arch, only with --with-binaries
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    binaries: &'a BTreeMap<String, String>,
+    errors: Vec<ErrorRecord>,
+}
+
+// One line of the report, with everything known about a package for a codename
+#[derive(Serialize)]
+struct Record<'a> {
+    environment: Option<&'a str>,
+    package: &'a str,
+    codename: &'a str,
+    section: Option<&'a str>,
+    severity: Option<&'static str>,
+    error_count: usize,
+    // Repos the package is expected in but missing from, by config id
+    missing: Vec<&'static str>,
+    // Versions found, by repo config id
+    versions: BTreeMap<&'static str, VersionRecord<'a>>,
+}
+
+// Writes one JSON object per package and codename, so consumers can process lines as they come
+pub fn jsonl_report<W: Write>(jsonl: &mut W, config: &Config, apt_infos: &AptInfos) -> Result<()> {
+    for ((package, codename), apt_info) in apt_infos.iter() {
+        let mut versions = BTreeMap::new();
+        for repo_kind in RepoKind::all() {
+            let Some(version) = apt_info.version(repo_kind) else {
+                continue;
+            };
+            let errors = version
+                .errors
+                .borrow()
+                .iter()
+                .map(|error| ErrorRecord {
+                    severity: error.severity.as_str(),
+                    message: error.message.clone(),
+                })
+                .collect();
+            versions.insert(
+                repo_kind.id(),
+                VersionRecord {
+                    version: &version.version,
+                    superseded: &version.superseded,
+                    binaries: &version.binaries,
+                    errors,
+                },
+            );
+        }
+        let record = Record {
+            environment: config.environment.as_deref(),
+            package,
+            codename: codename.as_str(),
+            section: apt_info.section(),
+            severity: apt_info.severity().map(|severity| severity.as_str()),
+            error_count: apt_info.error_count(),
+            missing: apt_info
+                .missing
+                .iter()
+                .map(|repo_kind| repo_kind.id())
+                .collect(),
+            versions,
+        };
+        serde_json::to_writer(&mut *jsonl, &record)?;
+        writeln!(jsonl)?;
+    }
+    Ok(())
+}
@@ -1,43 +1,260 @@
 use anyhow::{Context, Result, anyhow};
-use html_escape::encode_text;
+use clap::{Parser, Subcommand, ValueEnum};
+use flate2::write::GzEncoder;
+use futures_util::{StreamExt, stream};
+use html_escape::{encode_single_quoted_attribute, encode_text};
 use octocrab::Octocrab;
-use std::{cell::RefCell, collections::BTreeMap, fmt::Write as _, fs, io::Write};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Once},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
 
 mod apt;
+mod clusters;
 use self::apt::AptRepo;
+mod compare;
 mod config;
+mod diff;
 use self::config::*;
+mod explain;
+mod github;
+mod graph;
+mod jsonl;
+mod maintainers;
+mod model;
+mod profile;
+mod repos;
+mod snapshot;
+mod statsd;
+mod status;
+mod table;
+#[cfg(test)]
+mod test_server;
+mod text;
+mod version;
+mod versions;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // Behind Ubuntu
+    Info,
+    // Missing from a repo it should be promoted to
+    Warning,
+    // Older than in the previous codename of the same repo, or in an extra suite like -proposed
+    // than in the standard suite
+    Downgrade,
+    // Older than the target version in the manifest
+    Target,
+    // Published at different versions in two components of the same suite
+    Conflict,
+    // Listed in an index, but its .dsc is missing from the pool
+    Dangling,
+    // Published without any binaries built from it, only with --with-binaries
+    Unbuilt,
+    // Older than a repo it should be newer than, or an expected package is missing
+    Critical,
+}
+
+impl Severity {
+    // CSS class of cells with errors of this severity
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Downgrade => "downgrade",
+            Self::Target => "target",
+            Self::Conflict => "conflict",
+            Self::Dangling => "dangling",
+            Self::Unbuilt => "unbuilt",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AptError {
+    severity: Severity,
+    message: String,
+}
+
+// An index or Release that could not be fetched, recorded instead of aborting with --keep-going
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FailedFetch {
+    repo_kind: RepoKind,
+    // Unknown when the Release of a suite alias could not be fetched
+    codename: Option<Codename>,
+    suite: String,
+    component: Option<String>,
+    arch: Option<String>,
+    error: String,
+}
+
+impl FailedFetch {
+    fn new(
+        repo_kind: RepoKind,
+        codename: Option<Codename>,
+        suite: &str,
+        component: Option<&str>,
+        arch: Option<&str>,
+        err: &anyhow::Error,
+    ) -> Self {
+        let this = Self {
+            repo_kind,
+            codename,
+            suite: suite.to_string(),
+            component: component.map(|component| component.to_string()),
+            arch: arch.map(|arch| arch.to_string()),
+            error: format!("{err:#}"),
+        };
+        log::error!("failed to fetch {}: {}", this.location(), this.error);
+        this
+    }
+
+    // Where the fetch was, like release noble/main/amd64
+    pub fn location(&self) -> String {
+        let mut location = format!("{} {}", self.repo_kind.as_str(), self.suite);
+        for part in [&self.component, &self.arch].into_iter().flatten() {
+            location.push('/');
+            location.push_str(part);
+        }
+        location
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AptVersion {
     repo_kind: RepoKind,
     codename: Codename,
     version: String,
+    // Suite and component the version was found in
+    #[serde(default)]
+    suite: String,
+    #[serde(default)]
+    component: String,
     directory: Option<String>,
-    errors: RefCell<Vec<String>>,
+    // Branch the source was built from, if its Vcs-Git field names one
+    branch: Option<String>,
+    section: Option<String>,
+    // Maintainer field of the source, like "Name <email>"
+    #[serde(default)]
+    maintainer: Option<String>,
+    // Lower versions published at the same time, only recorded with --all-versions
+    superseded: Vec<String>,
+    // Highest binary version built from this source by arch, only with --with-binaries
+    binaries: BTreeMap<String, String>,
+    // Virtual packages provided by binaries built from this source, only with --with-binaries
+    #[serde(default)]
+    provides: BTreeSet<String>,
+    // Pool paths of the .debs at the highest binary version by arch, only with --with-binaries
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    debs: BTreeMap<String, Vec<String>>,
+    // Unix timestamp of the GitHub commit the source was built from, only with --commit-dates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commit_date: Option<i64>,
+    errors: RefCell<Vec<AptError>>,
 }
 
+// Repos built by Pop's CI from GitHub commits, whose versions link to the commit
+const COMMIT_REPO_KINDS: [RepoKind; 3] = [
+    RepoKind::Release,
+    RepoKind::Staging,
+    RepoKind::StagingUbuntu,
+];
+
 impl AptVersion {
-    fn github_commit(&self) -> Option<String> {
+    // Path of the .dsc in the pool, named from the package and the version without its epoch as
+    // dpkg-source names it
+    fn dsc_path(&self, package: &str) -> Option<String> {
+        let directory = self.directory.as_ref()?;
+        let version = self
+            .version
+            .split_once(':')
+            .map_or(self.version.as_str(), |(_epoch, version)| version);
+        Some(format!("{directory}/{package}_{version}.dsc"))
+    }
+
+    // GitHub repo and commit the source was built from, found from its Directory in the pool.
+    // Mirrors with another layout have none.
+    fn github_repo_commit(&self) -> Option<(&str, &str)> {
         let directory = self.directory.as_ref()?;
         let mut parts = directory.split('/');
-        assert_eq!(parts.next()?, "pool");
+        (parts.next()? == "pool").then_some(())?;
         let _codename = parts.next()?;
         let repo = parts.next()?;
         let commit = parts.next()?;
+        Some((repo, commit))
+    }
+
+    fn github_commit(&self) -> Option<String> {
+        let (repo, commit) = self.github_repo_commit()?;
         Some(format!(
             "https://github.com/{GITHUB_ORG}/{repo}/commit/{commit}"
         ))
     }
 
-    fn html_cell<W: Write>(&self, html: &mut W, package: &str) -> Result<()> {
+    // URL of the .dsc in the pool of the repo, the same one --check-pool requests
+    fn dsc_url(&self, config: &Config, package: &str) -> Option<String> {
+        let path = self.dsc_path(package)?;
+        let url = config
+            .repo_url(self.repo_kind)
+            .join(&apt::encode_path(&path))
+            .ok()?;
+        Some(url.to_string())
+    }
+
+    // URLs of the .debs built from this source by arch, skipping paths that are not valid URLs
+    fn deb_urls(&self, config: &Config) -> BTreeMap<&str, Vec<String>> {
+        let repo_url = config.repo_url(self.repo_kind);
+        self.debs
+            .iter()
+            .map(|(arch, filenames)| {
+                let urls = filenames
+                    .iter()
+                    .filter_map(|filename| repo_url.join(&apt::encode_path(filename)).ok())
+                    .map(|url| url.to_string())
+                    .collect();
+                (arch.as_str(), urls)
+            })
+            .collect()
+    }
+
+    // Only the Pop repos have sources from GitHub, with the repo name in the pool directory
+    fn github_branch(&self, branch: &str) -> Option<String> {
+        if matches!(
+            self.repo_kind,
+            RepoKind::Stable | RepoKind::PreStable | RepoKind::Ubuntu
+        ) {
+            return None;
+        }
+        let directory = self.directory.as_ref()?;
+        let repo = directory.split('/').nth(2)?;
+        Some(format!(
+            "https://github.com/{GITHUB_ORG}/{repo}/tree/{}",
+            urlencoding::encode(branch)
+        ))
+    }
+
+    fn html_cell<W: Write>(
+        &self,
+        html: &mut W,
+        args: &Args,
+        config: &Config,
+        package: &str,
+    ) -> Result<()> {
         let errors = self.errors.borrow();
-        if errors.is_empty() {
-            writeln!(html, "<td>",)?;
-        } else {
-            writeln!(html, "<td class='error'>",)?;
+        match errors.iter().map(|error| error.severity).max() {
+            Some(severity) => writeln!(html, "<td class='{}'>", severity.as_str())?,
+            None => writeln!(html, "<td>",)?,
         }
-        let url_opt = match self.repo_kind {
+        let default_url = match self.repo_kind {
             RepoKind::Stable => Some(format!(
                 "https://launchpad.net/~system76-dev/+archive/ubuntu/stable/+packages?field.name_filter={}&field.status_filter=published&field.series_filter={}",
                 urlencoding::encode(package),
@@ -54,10 +271,21 @@ impl AptVersion {
             )),
             _ => self.github_commit(),
         };
+        let version_link = config.version_link(self.repo_kind);
+        let dsc_url = match version_link {
+            VersionLink::Default => None,
+            // Without a Directory there is no .dsc to link, so fall back to the default link
+            VersionLink::Dsc | VersionLink::Both => self.dsc_url(config, package),
+        };
+        let url_opt = match version_link {
+            VersionLink::Dsc => dsc_url.clone().or(default_url),
+            VersionLink::Default | VersionLink::Both => default_url,
+        };
         if let Some(url) = url_opt {
             writeln!(
                 html,
-                "<a href='{url}'>{}</a>",
+                "<a href='{}'>{}</a>",
+                encode_single_quoted_attribute(&url),
                 // Allows version to line break at punctuation
                 encode_text(&self.version)
                     .replace("~", "~&#8203;")
@@ -67,15 +295,48 @@ impl AptVersion {
         } else {
             writeln!(html, "{}", encode_text(&self.version))?;
         }
+        if version_link == VersionLink::Both
+            && let Some(dsc_url) = &dsc_url
+        {
+            writeln!(
+                html,
+                "<br/><small><a href='{}'>.dsc</a></small>",
+                encode_single_quoted_attribute(dsc_url)
+            )?;
+        }
+        if let Some(branch) = &self.branch {
+            match self.github_branch(branch) {
+                Some(url) => writeln!(
+                    html,
+                    "<br/><small><a href='{}'>{}</a></small>",
+                    encode_single_quoted_attribute(&url),
+                    encode_text(branch)
+                )?,
+                None => writeln!(html, "<br/><small>{}</small>", encode_text(branch))?,
+            }
+        }
+        if let Some(commit_date) = self.commit_date {
+            // The date, unlike the days since it, is the same on every run
+            if args.reproducible {
+                let date = utc_date(commit_date, "%Y-%m-%d");
+                writeln!(html, "<br/><small>Committed {date}</small>")?;
+            } else {
+                let days = (chrono::Utc::now().timestamp() - commit_date) / 86400;
+                writeln!(html, "<br/><small>Committed {days} days ago</small>")?;
+            }
+        }
+        for superseded in self.superseded.iter() {
+            writeln!(html, "<br/><small>Also {}</small>", encode_text(superseded))?;
+        }
         for error in errors.iter() {
-            writeln!(html, "<br/>{}", encode_text(error))?;
+            writeln!(html, "<br/>{}", encode_text(&error.message))?;
         }
         writeln!(html, "</td>")?;
         Ok(())
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct AptInfo {
     release: Option<AptVersion>,
     staging: Option<AptVersion>,
@@ -83,6 +344,13 @@ pub struct AptInfo {
     stable: Option<AptVersion>,
     pre_stable: Option<AptVersion>,
     ubuntu: Option<AptVersion>,
+    // Highest Ubuntu version in each pocket, keyed by suite suffix
+    ubuntu_pockets: BTreeMap<String, AptVersion>,
+    // Highest version in each extra suite of a Pop repo, like noble-proposed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_suites: Vec<AptVersion>,
+    // Repos where this package is expected but was not found
+    missing: Vec<RepoKind>,
 }
 
 impl AptInfo {
@@ -107,37 +375,651 @@ impl AptInfo {
             RepoKind::Ubuntu => &mut self.ubuntu,
         }
     }
+
+    pub fn section(&self) -> Option<&str> {
+        RepoKind::all()
+            .into_iter()
+            .find_map(|repo_kind| self.version(repo_kind).as_ref()?.section.as_deref())
+    }
+
+    // Maintainer from the first repo that has one, Pop repos coming before Ubuntu
+    pub fn maintainer(&self) -> Option<&str> {
+        RepoKind::all()
+            .into_iter()
+            .find_map(|repo_kind| self.version(repo_kind).as_ref()?.maintainer.as_deref())
+    }
+
+    // Highest severity of all errors for this package, missing expected packages are critical
+    pub fn severity(&self) -> Option<Severity> {
+        let mut severity = if self.missing.is_empty() {
+            None
+        } else {
+            Some(Severity::Critical)
+        };
+        for repo_kind in RepoKind::all() {
+            if let Some(version) = self.version(repo_kind) {
+                for error in version.errors.borrow().iter() {
+                    severity = severity.max(Some(error.severity));
+                }
+            }
+        }
+        severity
+    }
+
+    pub fn error_count(&self) -> usize {
+        let mut errors = self.missing.len();
+        for repo_kind in RepoKind::all() {
+            if let Some(version) = self.version(repo_kind) {
+                errors += version.errors.borrow().len();
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List packages in Release that are missing from Staging for the next codename
+    ForwardPorts,
+    /// List packages whose versions differ between two repos
+    Compare {
+        repo_a: RepoKind,
+        repo_b: RepoKind,
+        #[arg(long)]
+        codename: Codename,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// List packages in repo A that are missing from repo B, regardless of version
+    Missing {
+        repo_a: RepoKind,
+        repo_b: RepoKind,
+        #[arg(long)]
+        codename: Codename,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the Release metadata of every repo without fetching package lists
+    Status,
+    /// Print every repo with its URL, codenames, suites, and archs as configured, without
+    /// fetching anything
+    ListRepos,
+    /// Print which repos must be newer than which as a Graphviz DOT graph
+    Graph,
+    /// Explain which versions were compared for a package and why each error was found
+    Explain { package: String, codename: Codename },
+    /// List every version of a package across codenames and repos, with their errors
+    Versions { package: String },
+    /// Count packages and packages with errors by maintainer, most errors first
+    Maintainers {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Group errors by the repo, suite, and component of the version they are on and their
+    /// severity, largest groups first, to find a broken component behind many errors
+    ErrorClusters {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// List versions that differ from a model saved with --save-model by another instance, to
+    /// find mirrors that disagree
+    Diff {
+        /// URL or file of the other model
+        other: String,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum Format {
+    /// index.html with GitHub PR counts and package tables
+    #[default]
+    Html,
+    /// index.txt with errors grouped by repo, for email bodies
+    Text,
+    /// index.jsonl with one JSON object per package and codename
+    Jsonl,
+    /// snapshot.txt with sorted package, codename, repo, and version lines, for tracking in git
+    SnapshotTxt,
+}
+
+#[derive(Parser)]
+#[command(about, version)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Config file to load, may be repeated with later files overriding earlier ones
+    #[arg(long = "config", value_name = "FILE")]
+    configs: Vec<PathBuf>,
+
+    /// Override a config value after loading config files, like repos.staging.url=URL, may be
+    /// repeated. The value is parsed as TOML, or else taken as a string.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// Omit run-varying content like the timestamp, which is written next to the report in
+    /// index.html.timestamp instead, so identical data produces an identical report
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Only include packages in this section, like admin, libs, or kernel
+    #[arg(long)]
+    section: Option<String>,
+
+    /// Only include packages for this codename, which also scopes --max-errors to it
+    #[arg(long)]
+    codename: Option<Codename>,
+
+    /// Exit with an error after writing the report if it has more than this many errors
+    #[arg(long, value_name = "COUNT")]
+    max_errors: Option<usize>,
+
+    /// Print the control stanzas parsed for this package to stderr
+    #[arg(long, value_name = "PACKAGE")]
+    dump: Option<String>,
+
+    /// Query GitHub PR counts with a single GraphQL request instead of one search per filter,
+    /// which also shows the oldest PR, review decisions, and requested teams of each filter.
+    /// These details are not cached.
+    #[arg(long)]
+    github_graphql: bool,
+
+    /// Show how long ago the GitHub commit of each Pop version was made in the HTML report,
+    /// looked up with the GitHub API and cached in commit_date_cache
+    #[arg(long)]
+    commit_dates: bool,
+
+    /// Render a separate table for each codename instead of one combined table
+    #[arg(long)]
+    split_codenames: bool,
+
+    /// Fetch GitHub PR counts even if cached counts are still fresh
+    #[arg(long)]
+    refresh_github: bool,
+
+    /// Write how long fetching, decoding, parsing, and aggregating took as folded stacks, for
+    /// inferno-flamegraph or flamegraph.pl
+    #[arg(long, value_name = "PATH")]
+    profile: Option<PathBuf>,
+
+    /// Render GitHub PR counts from a github_cache file saved by an earlier run, however old,
+    /// and make no GitHub requests, not even for --commit-dates
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["refresh_github", "github_graphql"])]
+    github_snapshot: Option<PathBuf>,
+
+    /// Include the versions being compared in error messages
+    #[arg(long)]
+    verbose_errors: bool,
+
+    /// Compare repos by upstream version only, ignoring Debian revisions and Ubuntu or Pop
+    /// suffixes, so only genuine upstream differences are reported
+    #[arg(long)]
+    upstream_only: bool,
+
+    /// Show a column for each Ubuntu pocket, like -security, instead of only the highest version
+    #[arg(long)]
+    split_ubuntu_pockets: bool,
+
+    /// Check that the .dsc of each version in a Pop repo is still in the pool with a HEAD
+    /// request, flagging index entries whose files were removed
+    #[arg(long)]
+    check_pool: bool,
+
+    /// Add a column with the version in the Ubuntu development series, flagging packages where
+    /// it is ahead of Release
+    #[arg(long)]
+    ubuntu_devel: bool,
+
+    /// Title of the report, overriding the title config
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Environment shown in a banner on the report, like prod or staging, overriding the
+    /// environment config
+    #[arg(long)]
+    environment: Option<String>,
+
+    /// Show every published version of a package in a Pop repo, not only the highest, to find
+    /// superseded versions that should have been removed
+    #[arg(long)]
+    all_versions: bool,
+
+    /// Also fetch the Packages index of each arch a component builds for, adding up to one
+    /// request per component and arch
+    #[arg(long)]
+    with_binaries: bool,
+
+    /// Flag versions that are older than in the prior codename of the same repo, the previous
+    /// one unless prior_codenames in the config says otherwise
+    #[arg(long)]
+    check_codename_order: bool,
+
+    /// Also list Ubuntu packages no Pop repo has, if they match carry_candidates in the config
+    #[arg(long)]
+    carry_candidates: bool,
+
+    /// When fetching a Release or index fails, list it in the report and carry on without it
+    /// instead of aborting
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+
+    /// Save the fetched packages and errors to this file, to render again later with --from
+    #[arg(long, value_name = "FILE")]
+    save_model: Option<PathBuf>,
+
+    /// Render from packages and errors saved with --save-model instead of fetching. GitHub PR
+    /// counts and the Ubuntu devel column are skipped, as they need network access.
+    #[arg(long, value_name = "FILE", conflicts_with = "save_model")]
+    from: Option<PathBuf>,
+
+    /// File to write the report to, or - for stdout. Defaults to index.html, index.txt,
+    /// index.jsonl, or snapshot.txt depending on the format, with .gz added with --compress
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Gzip the report, for web servers to serve with Content-Encoding: gzip
+    #[arg(long)]
+    compress: bool,
+
+    /// Count repo warnings and failed fetches as errors, in the summary and for --max-errors.
+    /// Without --max-errors, exit with an error if there are any
+    #[arg(long)]
+    warnings_as_errors: bool,
+}
+
+// Finds the codename a suite alias like devel currently points to, from its Release. Fails with
+// a warning if it is not a codename tracked for the repo, as there is nowhere to show it.
+fn resolve_alias(
+    repo_kind: RepoKind,
+    alias: &str,
+    release: &apt::Release,
+) -> Result<Codename, String> {
+    let Some(name) = &release.codename else {
+        return Err(format!("{alias}: Release has no Codename"));
+    };
+    match Codename::from_str(name, true) {
+        Ok(codename) if repo_kind.codenames().contains(&codename) => {
+            log::info!("{} {alias} resolved to {name}", repo_kind.as_str());
+            Ok(codename)
+        }
+        _ => Err(format!(
+            "{alias} resolved to {name}, which is not a tracked codename"
+        )),
+    }
+}
+
+// Whether a source with these Architectures builds binaries in a Packages index for arch
+fn source_has_arch(source_archs: &[String], arch: &str) -> bool {
+    source_archs.iter().any(|source_arch| {
+        source_arch == arch
+            || source_arch == "any"
+            || source_arch == "linux-any"
+            || source_arch.strip_prefix("any-") == Some(arch)
+            || source_arch.strip_prefix("linux-") == Some(arch)
+    })
+}
+
+// Archs with Packages indices worth fetching for a component, skipping archs none of its
+// sources build for. Architecture: all packages are in every index, so one arch is enough.
+fn binary_archs(sources: &[apt::Source], archs: &[String]) -> Vec<String> {
+    let mut needed: Vec<String> = archs
+        .iter()
+        .filter(|arch| {
+            sources.iter().any(|source| {
+                source
+                    .archs
+                    .as_ref()
+                    .is_none_or(|source_archs| source_has_arch(source_archs, arch))
+            })
+        })
+        .cloned()
+        .collect();
+    let has_all = sources.iter().any(|source| {
+        source
+            .archs
+            .as_ref()
+            .is_some_and(|source_archs| source_archs.iter().any(|arch| arch == "all"))
+    });
+    if needed.is_empty()
+        && has_all
+        && let Some(arch) = archs.first()
+    {
+        needed.push(arch.clone());
+    }
+    needed
+}
+
+// Flags versions in Pop repos whose .dsc is no longer in the pool. Checks share
+// max_concurrent_fetches, and ones that fail for another reason than a 404 are returned as
+// warnings, as they say nothing about the pool.
+async fn check_pool(args: &Args, config: &Config, apt_infos: &AptInfos) -> Result<RepoWarnings> {
+    let mut repos = BTreeMap::new();
+    let mut checks = Vec::new();
+    for repo_kind in RepoKind::all() {
+        if repo_kind == RepoKind::Ubuntu {
+            continue;
+        }
+        repos.insert(repo_kind, AptRepo::new(config, repo_kind)?);
+        for ((package, _codename), apt_info) in apt_infos.iter() {
+            if let Some(version) = apt_info.version(repo_kind)
+                && let Some(path) = version.dsc_path(package)
+            {
+                checks.push((repo_kind, path, version));
+            }
+        }
+    }
+    log::info!("checking {} pool entries", checks.len());
+    let results: Vec<_> = stream::iter(checks.iter())
+        .map(|(repo_kind, path, _version)| repos[repo_kind].pool_file_exists(path))
+        .buffered(config.max_concurrent_fetches)
+        .collect()
+        .await;
+    let mut warnings = RepoWarnings::new();
+    for ((repo_kind, path, version), result) in checks.iter().zip(results) {
+        match result {
+            Ok(true) => {}
+            Ok(false) => {
+                let mut message = format!("Dangling pool entry: {path} not found");
+                if args.verbose_errors {
+                    message = format!("{} {message}", repo_kind.as_str());
+                }
+                version.errors.borrow_mut().push(AptError {
+                    severity: Severity::Dangling,
+                    message,
+                });
+            }
+            Err(err) => {
+                let message = format!("failed to check {path}: {err:#}");
+                log::warn!("{}: {message}", repo_kind.as_str());
+                warnings.push((*repo_kind, message));
+            }
+        }
+    }
+    Ok(warnings)
 }
 
+// Records the binary versions of each source and flags archs with binaries built from a
+// different version of the source than the one published, ignoring binNMU suffixes. Returns every
+// source version with at least one binary built from it.
+fn check_binaries(
+    apt_infos: &mut AptInfos,
+    binaries: Vec<(RepoKind, Codename, String, Vec<apt::Package>)>,
+) -> BTreeSet<SourceKey> {
+    let mut built = BTreeSet::new();
+    for (repo_kind, codename, index_arch, packages) in binaries {
+        for package in packages.iter() {
+            let (Some((source, source_version)), Some(version)) =
+                (package.source_version(), package.version.as_ref())
+            else {
+                continue;
+            };
+            built.insert((
+                repo_kind,
+                codename,
+                source.to_string(),
+                source_version.to_string(),
+            ));
+            let Some(apt_version) = apt_infos
+                .get_mut(&(source.to_string(), codename))
+                .and_then(|apt_info| apt_info.version_mut(repo_kind).as_mut())
+            else {
+                continue;
+            };
+            // Architecture: all packages are in every index, so use their own arch
+            let arch = package.arch.clone().unwrap_or_else(|| index_arch.clone());
+            let ordering = apt_version
+                .binaries
+                .get(&arch)
+                .map_or(std::cmp::Ordering::Greater, |last| {
+                    deb_version::compare_versions(version, last)
+                });
+            if ordering == std::cmp::Ordering::Greater {
+                apt_version.binaries.insert(arch.clone(), version.clone());
+                apt_version.debs.remove(&arch);
+            }
+            // Every binary of the highest version, as a source often builds several
+            if ordering != std::cmp::Ordering::Less
+                && let Some(filename) = &package.filename
+            {
+                let debs = apt_version.debs.entry(arch.clone()).or_default();
+                if !debs.contains(filename) {
+                    debs.push(filename.clone());
+                }
+            }
+            if let Some(provides) = &package.provides {
+                apt_version.provides.extend(provides.iter().cloned());
+            }
+            if source_version != apt_version.version {
+                let message = format!("{arch} binaries built from {source_version}");
+                let mut errors = apt_version.errors.borrow_mut();
+                if !errors.iter().any(|error| error.message == message) {
+                    errors.push(AptError {
+                        severity: Severity::Warning,
+                        message,
+                    });
+                }
+            }
+        }
+    }
+    built
+}
+
+// Flags published versions without a single binary built from them, as the source landed but
+// nothing can be installed. Only sources that build for an arch whose Packages index was
+// fetched are expected to have binaries, and versions lower than the one kept are skipped.
+fn check_unbuilt(
+    args: &Args,
+    apt_infos: &AptInfos,
+    expected: Vec<SourceKey>,
+    built: &BTreeSet<SourceKey>,
+) {
+    for key in expected {
+        if built.contains(&key) {
+            continue;
+        }
+        let (repo_kind, codename, package, version) = key;
+        let Some(apt_version) = apt_infos
+            .get(&(package, codename))
+            .and_then(|apt_info| apt_info.version(repo_kind).as_ref())
+        else {
+            continue;
+        };
+        if apt_version.version != version {
+            continue;
+        }
+        let mut message = format!("No binaries built from {version}");
+        if args.verbose_errors {
+            message = format!("{} {message}", repo_kind.as_str());
+        }
+        let mut errors = apt_version.errors.borrow_mut();
+        if !errors.iter().any(|error| error.message == message) {
+            errors.push(AptError {
+                severity: Severity::Unbuilt,
+                message,
+            });
+        }
+    }
+}
+
+// A source version in a repo and codename, as (repo, codename, source, version)
+type SourceKey = (RepoKind, Codename, String, String);
+
 // Uses a BTreeMap so it stays sorted
 type AptInfos = BTreeMap<(String, Codename), AptInfo>;
 
-async fn apt_infos() -> Result<AptInfos> {
+// Problems with a repo as a whole rather than a package
+type RepoErrors = Vec<(RepoKind, String)>;
+// Problems with a repo that do not make its packages wrong, only counted with
+// --warnings-as-errors
+type RepoWarnings = Vec<(RepoKind, String)>;
+
+// Fetches skipped with --keep-going, kept apart from errors in the data
+type FailedFetches = Vec<FailedFetch>;
+
+// Everything found by fetching the repos, which all reports are made from
+pub struct Fetched {
+    apt_infos: AptInfos,
+    repo_errors: RepoErrors,
+    repo_warnings: RepoWarnings,
+    failed_fetches: FailedFetches,
+    // Date of the newest Release of each repo, as a Unix timestamp
+    updated: BTreeMap<RepoKind, i64>,
+    // Suites of each repo whose Release is past its Valid-Until
+    expired: BTreeMap<RepoKind, Vec<String>>,
+}
+
+// Hours since the newest Release of a repo and its max_age_hours, None if it has no
+// max_age_hours or no Release date was found
+fn repo_age(
+    config: &Config,
+    updated: &BTreeMap<RepoKind, i64>,
+    repo_kind: RepoKind,
+) -> Option<(i64, u64)> {
+    let max_age_hours = config.max_age_hours(repo_kind)?;
+    let updated = updated.get(&repo_kind)?;
+    let hours = (chrono::Utc::now().timestamp() - updated) / 3600;
+    Some((hours, max_age_hours))
+}
+
+// Date of a Unix timestamp in UTC, for output that must not change with the time of the run
+fn utc_date(timestamp: i64, format: &str) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0).map_or_else(
+        || timestamp.to_string(),
+        |date| date.format(format).to_string(),
+    )
+}
+
+// Limits index fetches to max_concurrent_fetches. With fetch_ramp_seconds set, it starts with
+// fetch_ramp_start permits and gains the rest evenly over that time, so a cold run does not open
+// every connection in the same instant.
+fn fetch_limit(config: &Config) -> Arc<Semaphore> {
+    let max = config.max_concurrent_fetches;
+    let start = config.fetch_ramp_start;
+    if config.fetch_ramp_seconds == 0 || start >= max {
+        return Arc::new(Semaphore::new(max));
+    }
+    let semaphore = Arc::new(Semaphore::new(start));
+    let steps = max - start;
+    let interval = Duration::from_secs(config.fetch_ramp_seconds) / steps as u32;
+    log::debug!(
+        "ramping fetches from {start} to {max} over {}s",
+        config.fetch_ramp_seconds
+    );
+    tokio::spawn({
+        let semaphore = semaphore.clone();
+        async move {
+            for _ in 0..steps {
+                tokio::time::sleep(interval).await;
+                semaphore.add_permits(1);
+            }
+        }
+    });
+    semaphore
+}
+
+#[tracing::instrument(skip_all)]
+async fn apt_infos(args: &Args, config: &Config) -> Result<Fetched> {
     log::info!("fetching repository data in parallel");
+    // Shared by index fetches of all repos, so enabling more of them does not flood mirrors
+    let fetch_limit = fetch_limit(config);
+    let mut repo_warnings = RepoWarnings::new();
     let mut release_tasks = Vec::new();
     for repo_kind in RepoKind::all() {
-        let repo = AptRepo::new(repo_kind.url());
+        let repo = AptRepo::new(config, repo_kind)?.with_dump(args.dump.clone());
         let mut repo_tasks = Vec::new();
+        if repo_kind.codenames().is_empty() || config.suite_kinds(repo_kind).is_empty() {
+            let message = "no codenames or suites to fetch, its column will be empty".to_string();
+            log::warn!("{}: {message}", repo_kind.as_str());
+            repo_warnings.push((repo_kind, message));
+        }
         for codename in repo_kind.codenames() {
-            for suite in repo_kind.suites(*codename) {
-                repo_tasks.push((codename, suite, {
+            for suite in config.suites(repo_kind, *codename) {
+                let pocket = suite.kind().as_str().to_string();
+                let suite = suite.to_string();
+                repo_tasks.push((Some(*codename), suite.clone(), pocket, {
                     let repo = repo.clone();
-                    tokio::spawn(async move { repo.release(&suite.to_string()).await })
+                    tokio::spawn(async move { repo.release(&suite).await })
                 }));
             }
         }
+        // The codename of an alias is only known once its Release is fetched
+        for alias in config.suite_aliases(repo_kind) {
+            repo_tasks.push((None, alias.clone(), alias.clone(), {
+                let repo = repo.clone();
+                let alias = alias.clone();
+                tokio::spawn(async move { repo.release(&alias).await })
+            }));
+        }
         release_tasks.push((repo_kind, repo_tasks));
     }
 
+    let mut repo_errors = RepoErrors::new();
+    let mut failed_fetches = FailedFetches::new();
+    let mut updated = BTreeMap::<RepoKind, i64>::new();
+    let mut expired = BTreeMap::<RepoKind, Vec<String>>::new();
     let mut tasks = Vec::new();
     for (repo_kind, release_repo_tasks) in release_tasks {
-        let repo = AptRepo::new(repo_kind.url());
+        let repo = AptRepo::new(config, repo_kind)?.with_dump(args.dump.clone());
         let mut repo_tasks = Vec::new();
-        for (codename, suite, release_task) in release_repo_tasks {
+        for (codename, suite, pocket, release_task) in release_repo_tasks {
             let mut suite_tasks = Vec::new();
-            let releases = release_task.await??;
+            let releases = match release_task.await? {
+                Ok(ok) => ok,
+                Err(err) if args.keep_going => {
+                    failed_fetches.push(FailedFetch::new(
+                        repo_kind, codename, &suite, None, None, &err,
+                    ));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
             assert_eq!(releases.len(), 1);
+            let codename = match codename {
+                Some(codename) => codename,
+                None => match resolve_alias(repo_kind, &suite, &releases[0]) {
+                    Ok(codename) => codename,
+                    Err(message) => {
+                        log::warn!("{}: {message}", repo_kind.as_str());
+                        repo_warnings.push((repo_kind, message));
+                        continue;
+                    }
+                },
+            };
             for release in releases {
+                if let Some(timestamp) = release.timestamp() {
+                    let newest = updated.entry(repo_kind).or_insert(timestamp);
+                    *newest = (*newest).max(timestamp);
+                }
+                // apt would refuse this suite, so its versions are not what users get
+                if release.expired() {
+                    let message = format!(
+                        "{suite}: Release expired, valid until {}",
+                        release.valid_until.as_deref().unwrap_or_default()
+                    );
+                    log::error!("{}: {message}", repo_kind.as_str());
+                    repo_errors.push((repo_kind, message));
+                    expired.entry(repo_kind).or_default().push(suite.clone());
+                }
+                let components = release.components.as_deref().unwrap_or_default();
+                for expected in config.expected_components(repo_kind, codename) {
+                    if !components.contains(expected) {
+                        let message = format!("{suite}: missing component {expected}");
+                        log::error!("{}: {message}", repo_kind.as_str());
+                        repo_errors.push((repo_kind, message));
+                    }
+                }
+                let sizes = Arc::new(release.files.clone());
                 for component in release
                     .components
                     .as_ref()
@@ -145,20 +1027,25 @@ async fn apt_infos() -> Result<AptInfos> {
                 {
                     let sources_task = {
                         let repo = repo.clone();
+                        let suite = suite.to_string();
                         let component = component.clone();
-                        tokio::spawn(
-                            async move { repo.sources(&suite.to_string(), &component).await },
-                        )
+                        let split = release.split_sources(&component);
+                        let sizes = sizes.clone();
+                        let fetch_limit = fetch_limit.clone();
+                        tokio::spawn(async move {
+                            let _permit = fetch_limit.acquire_owned().await?;
+                            repo.sources(&suite, &component, &split, &sizes).await
+                        })
                     };
 
-                    let mut arch_tasks = Vec::new();
+                    let mut archs = Vec::new();
                     for arch in release
                         .archs
                         .as_ref()
                         .ok_or(anyhow!("release missing archs"))?
                     {
                         let mut allowed = false;
-                        for allowed_arch in repo_kind.allowed_archs() {
+                        for allowed_arch in config.allowed_archs(repo_kind, codename) {
                             if arch == allowed_arch.as_str() {
                                 allowed = true;
                                 break;
@@ -168,266 +1055,1882 @@ async fn apt_infos() -> Result<AptInfos> {
                             continue;
                         }
 
-                        //TODO: use Packages data (slower)
-                        if false {
-                            arch_tasks.push((arch.clone(), {
-                                let repo = repo.clone();
-                                let component = component.clone();
-                                let arch = arch.clone();
-                                tokio::spawn(async move {
-                                    repo.packages(&suite.to_string(), &component, &arch).await
-                                })
-                            }));
+                        // Binaries of an extra suite would be checked against the version users
+                        // get, so they are left out
+                        if args.with_binaries && !config.pop_extra_suite(repo_kind, &pocket) {
+                            archs.push(arch.clone());
                         }
                     }
 
-                    suite_tasks.push((component.clone(), sources_task, arch_tasks));
+                    suite_tasks.push((component.clone(), sources_task, archs, sizes.clone()));
                 }
             }
-            repo_tasks.push((codename, suite, suite_tasks));
+            repo_tasks.push((codename, suite, pocket, suite_tasks));
         }
-        tasks.push((repo_kind, repo_tasks));
+        tasks.push((repo_kind, repo, repo_tasks));
     }
 
     let mut apt_infos = AptInfos::new();
-    for (repo_kind, repo_tasks) in tasks {
-        println!("{:?}", repo_kind);
-        for (codename, suite, suite_tasks) in repo_tasks {
-            println!("\t{}", suite);
-            for (component, sources_task, arch_tasks) in suite_tasks {
-                let sources = sources_task.await??;
-                println!("\t\t{}: {} sources", component, sources.len());
+    let mut binaries = Vec::new();
+    let mut expected_binaries = Vec::new();
+    let mut component_conflicts = Vec::new();
+    for (repo_kind, repo, repo_tasks) in tasks {
+        eprintln!("{:?}", repo_kind);
+        for (codename, suite, pocket, suite_tasks) in repo_tasks {
+            eprintln!("\t{}", suite);
+            // Highest version of each source by component, to find sources in more than one
+            let mut component_versions = BTreeMap::<String, BTreeMap<String, String>>::new();
+            for (component, sources_task, archs, sizes) in suite_tasks {
+                let sources = match sources_task.await? {
+                    Ok(ok) => ok,
+                    Err(err) if args.keep_going => {
+                        failed_fetches.push(FailedFetch::new(
+                            repo_kind,
+                            Some(codename),
+                            &suite,
+                            Some(&component),
+                            None,
+                            &err,
+                        ));
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+                eprintln!("\t\t{}: {} sources", component, sources.len());
+                //TODO: check Ubuntu binaries too, their Packages are only counted for now
+                let mut arch_tasks = Vec::new();
+                for arch in binary_archs(&sources, &archs) {
+                    arch_tasks.push((arch.clone(), {
+                        let repo = repo.clone();
+                        let suite = suite.to_string();
+                        let component = component.clone();
+                        let sizes = sizes.clone();
+                        let fetch_limit = fetch_limit.clone();
+                        tokio::spawn(async move {
+                            let _permit = fetch_limit.acquire_owned().await?;
+                            repo.packages(&suite, &component, &arch, &sizes).await
+                        })
+                    }));
+                }
+                let aggregate = tracing::info_span!("aggregate").entered();
+                // Sources of Pop repos with their archs, to check they have binaries once the
+                // Packages indices are in
+                let mut source_archs = Vec::new();
                 for source in sources {
+                    let branch = source.vcs_branch().map(|branch| branch.to_string());
                     let Some(package) = source.package else {
                         continue;
                     };
                     let Some(version) = source.version else {
                         continue;
                     };
+                    let component_version = component_versions
+                        .entry(package.clone())
+                        .or_default()
+                        .entry(component.clone())
+                        .or_insert_with(|| version.clone());
+                    if deb_version::compare_versions(&version, component_version)
+                        == std::cmp::Ordering::Greater
+                    {
+                        *component_version = version.clone();
+                    }
+                    if repo_kind != RepoKind::Ubuntu && !arch_tasks.is_empty() {
+                        source_archs.push((package.clone(), version.clone(), source.archs.clone()));
+                    }
                     let apt_version = || AptVersion {
                         repo_kind,
-                        codename: *codename,
+                        codename,
                         version: version.clone(),
+                        suite: suite.clone(),
+                        component: component.clone(),
                         directory: source.directory.clone(),
+                        branch: branch.clone(),
+                        section: source.section.clone(),
+                        maintainer: source.maintainer.clone(),
+                        superseded: Vec::new(),
+                        binaries: BTreeMap::new(),
+                        provides: BTreeSet::new(),
+                        debs: BTreeMap::new(),
+                        commit_date: None,
                         errors: RefCell::new(Vec::new()),
                     };
-                    let entry = apt_infos.entry((package, *codename));
+                    if repo_kind == RepoKind::Ubuntu
+                        && args.carry_candidates
+                        && config.carry_candidate(&component, &package)
+                    {
+                        // Pop repos come first, so this only adds packages Pop does not have
+                        apt_infos.entry((package.clone(), codename)).or_default();
+                    }
+                    let entry = apt_infos.entry((package, codename));
                     match repo_kind {
                         RepoKind::Ubuntu => {
-                            // Only insert Ubuntu versions if a Pop version is found
-                            entry.and_modify(|apt_info| match &apt_info.ubuntu {
-                                Some(last) => {
-                                    if let std::cmp::Ordering::Greater =
-                                        deb_version::compare_versions(&version, &last.version)
-                                    {
-                                        apt_info.ubuntu = Some(apt_version());
+                            // Only insert Ubuntu versions if a Pop version is found, or for
+                            // carry candidates. Pockets left out of the comparison still get
+                            // their own column with --split-ubuntu-pockets.
+                            let compared = config.ubuntu_pocket_compared(&pocket);
+                            entry.and_modify(|apt_info| {
+                                if compared {
+                                    match &apt_info.ubuntu {
+                                        Some(last) => {
+                                            if let std::cmp::Ordering::Greater =
+                                                deb_version::compare_versions(
+                                                    &version,
+                                                    &last.version,
+                                                )
+                                            {
+                                                apt_info.ubuntu = Some(apt_version());
+                                            }
+                                        }
+                                        None => {
+                                            apt_info.ubuntu = Some(apt_version());
+                                        }
                                     }
                                 }
-                                None => {
-                                    apt_info.ubuntu = Some(apt_version());
+                                let newer =
+                                    apt_info.ubuntu_pockets.get(&pocket).is_none_or(|last| {
+                                        deb_version::compare_versions(&version, &last.version)
+                                            == std::cmp::Ordering::Greater
+                                    });
+                                if newer {
+                                    apt_info
+                                        .ubuntu_pockets
+                                        .insert(pocket.clone(), apt_version());
+                                }
+                            });
+                        }
+                        _ if config.pop_extra_suite(repo_kind, &pocket) => {
+                            // Kept apart from the version users get, so that a regression can be
+                            // flagged instead of replacing it. Only packages already found in
+                            // the standard suite, which is fetched first, are kept.
+                            entry.and_modify(|apt_info| {
+                                let last = apt_info.extra_suites.iter_mut().find(|last| {
+                                    last.repo_kind == repo_kind && last.suite == suite
+                                });
+                                match last {
+                                    Some(last) => {
+                                        if deb_version::compare_versions(&version, &last.version)
+                                            == std::cmp::Ordering::Greater
+                                        {
+                                            *last = apt_version();
+                                        }
+                                    }
+                                    None => apt_info.extra_suites.push(apt_version()),
                                 }
                             });
                         }
                         _ => {
+                            // Keep the highest version if more than one suite has the package
                             let apt_info = entry.or_default();
-                            let version = apt_info.version_mut(repo_kind);
-                            assert!(version.is_none());
-                            *version = Some(apt_version());
+                            let slot = apt_info.version_mut(repo_kind);
+                            let newer = slot.as_ref().is_none_or(|last| {
+                                deb_version::compare_versions(&version, &last.version)
+                                    == std::cmp::Ordering::Greater
+                            });
+                            if newer {
+                                let mut apt_version = apt_version();
+                                if args.all_versions
+                                    && let Some(last) = slot.take()
+                                {
+                                    apt_version.superseded = last.superseded;
+                                    apt_version.superseded.push(last.version);
+                                }
+                                *slot = Some(apt_version);
+                            } else if args.all_versions
+                                && let Some(last) = slot
+                                && last.version != version
+                                && !last.superseded.contains(&version)
+                            {
+                                last.superseded.push(version.clone());
+                            }
                         }
                     }
                 }
+                drop(aggregate);
+                let mut fetched_archs = Vec::new();
                 for (arch, packages_task) in arch_tasks {
-                    let packages = packages_task.await??;
+                    let packages = match packages_task.await? {
+                        Ok(ok) => ok,
+                        Err(err) if args.keep_going => {
+                            failed_fetches.push(FailedFetch::new(
+                                repo_kind,
+                                Some(codename),
+                                &suite,
+                                Some(&component),
+                                Some(&arch),
+                                &err,
+                            ));
+                            continue;
+                        }
+                        Err(err) => return Err(err),
+                    };
                     if !packages.is_empty() {
-                        println!("\t\t{}/{}: {} packages", component, arch, packages.len());
+                        eprintln!("\t\t{}/{}: {} packages", component, arch, packages.len());
+                    }
+                    // Checked once all suites are merged, as a later suite may have a newer source
+                    if repo_kind != RepoKind::Ubuntu {
+                        fetched_archs.push(arch.clone());
+                        binaries.push((repo_kind, codename, arch, packages));
+                    }
+                }
+                // Architecture: all packages are in every index, so any fetched arch will do
+                for (package, version, archs) in source_archs {
+                    let builds = archs.as_ref().is_none_or(|archs| {
+                        archs.iter().any(|arch| arch == "all")
+                            || fetched_archs
+                                .iter()
+                                .any(|arch| source_has_arch(archs, arch))
+                    });
+                    if builds && !fetched_archs.is_empty() {
+                        expected_binaries.push((repo_kind, codename, package, version));
                     }
                 }
             }
+            for (package, versions) in component_versions {
+                let mut distinct: Vec<_> = versions.values().collect();
+                distinct.dedup();
+                if distinct.len() > 1 {
+                    component_conflicts.push((
+                        repo_kind,
+                        codename,
+                        suite.clone(),
+                        package,
+                        versions,
+                    ));
+                }
+            }
+        }
+    }
+
+    tracing::info_span!("check_binaries").in_scope(|| {
+        let built = check_binaries(&mut apt_infos, binaries);
+        check_unbuilt(args, &apt_infos, expected_binaries, &built);
+    });
+
+    // A source in two components of a suite at different versions is a publishing mistake, which
+    // keeping the highest version would otherwise hide
+    for (repo_kind, codename, suite, package, versions) in component_conflicts {
+        let Some(version) = apt_infos
+            .get(&(package, codename))
+            .and_then(|apt_info| apt_info.version(repo_kind).as_ref())
+        else {
+            continue;
+        };
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|(component, version)| format!("{version} in {component}"))
+            .collect();
+        let mut message = format!("Conflicting versions in {suite}: {}", versions.join(", "));
+        if args.verbose_errors {
+            message = format!("{} {message}", repo_kind.as_str());
+        }
+        version.errors.borrow_mut().push(AptError {
+            severity: Severity::Conflict,
+            message,
+        });
+    }
+
+    if args.check_pool {
+        repo_warnings.extend(check_pool(args, config, &apt_infos).await?);
+    }
+
+    for repo_kind in RepoKind::all() {
+        if !apt_infos
+            .values()
+            .any(|apt_info| apt_info.version(repo_kind).is_some())
+        {
+            let message = "no packages found".to_string();
+            log::warn!("{}: {message}", repo_kind.as_str());
+            repo_warnings.push((repo_kind, message));
+        }
+    }
+
+    // Find expected packages that are missing entirely from a repo
+    for ((package, codename), repo_kinds) in config.expected_packages() {
+        let mut missing = Vec::new();
+        for repo_kind in repo_kinds {
+            if apt_infos
+                .get(&(package.to_string(), codename))
+                .is_some_and(|apt_info| apt_info.version(repo_kind).is_some())
+            {
+                continue;
+            }
+            if let Some(provider) = provider(&apt_infos, repo_kind, codename, package) {
+                log::info!(
+                    "{package} for {} is provided by {provider} in {}",
+                    codename.as_str(),
+                    repo_kind.as_str()
+                );
+                continue;
+            }
+            missing.push(repo_kind);
+        }
+        if !missing.is_empty() {
+            apt_infos
+                .entry((package.to_string(), codename))
+                .or_default()
+                .missing
+                .extend(missing);
         }
     }
 
     // Calculate errors
-    for ((_package, _codename), apt_info) in apt_infos.iter() {
+    for ((package, codename), apt_info) in apt_infos.iter() {
+        if let Some(target) = config.target_version(package, *codename) {
+            for repo_kind in RepoKind::all() {
+                if repo_kind == RepoKind::Ubuntu {
+                    continue;
+                }
+                let Some(version) = apt_info.version(repo_kind) else {
+                    continue;
+                };
+                if deb_version::compare_versions(&version.version, target)
+                    == std::cmp::Ordering::Less
+                {
+                    let message = if args.verbose_errors {
+                        format!(
+                            "{} {} below target {target}",
+                            repo_kind.as_str(),
+                            version.version
+                        )
+                    } else {
+                        format!("Below target {target}")
+                    };
+                    version.errors.borrow_mut().push(AptError {
+                        severity: Severity::Target,
+                        message,
+                    });
+                }
+            }
+        }
         for repo_kind in RepoKind::all() {
             for older_kind in repo_kind.must_be_newer_than() {
                 if let Some(older_version) = apt_info.version(older_kind) {
                     if let Some(version) = apt_info.version(repo_kind) {
-                        if let std::cmp::Ordering::Less =
-                            deb_version::compare_versions(&version.version, &older_version.version)
-                        {
+                        if let std::cmp::Ordering::Less = version::compare_versions(
+                            args.upstream_only,
+                            &version.version,
+                            &older_version.version,
+                        ) {
+                            let message = if args.verbose_errors {
+                                format!(
+                                    "{} {} older than {} {}",
+                                    repo_kind.as_str(),
+                                    version.version,
+                                    older_kind.as_str(),
+                                    older_version.version
+                                )
+                            } else {
+                                format!("Older than {}", older_kind.as_str())
+                            };
+                            let severity = match older_kind {
+                                RepoKind::Ubuntu => Severity::Info,
+                                _ => Severity::Critical,
+                            };
                             version
                                 .errors
                                 .borrow_mut()
-                                .push(format!("Older than {}", older_kind.as_str()));
+                                .push(AptError { severity, message });
                         }
                     } else if !matches!(older_kind, RepoKind::Ubuntu) {
-                        older_version
-                            .errors
-                            .borrow_mut()
-                            .push(format!("Not in {}", repo_kind.as_str()));
+                        let message = if args.verbose_errors {
+                            format!(
+                                "{} {} not in {}",
+                                older_kind.as_str(),
+                                older_version.version,
+                                repo_kind.as_str()
+                            )
+                        } else {
+                            format!("Not in {}", repo_kind.as_str())
+                        };
+                        older_version.errors.borrow_mut().push(AptError {
+                            severity: Severity::Warning,
+                            message,
+                        });
                     }
                 }
             }
         }
     }
 
-    Ok(apt_infos)
-}
+    check_extra_suites(args, &apt_infos);
+    if args.check_codename_order {
+        check_codename_order(args, config, &apt_infos);
+    }
+    if args.carry_candidates {
+        check_carry_candidates(&apt_infos);
+    }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    for repo_kind in RepoKind::all() {
+        if let Some((hours, max_age_hours)) = repo_age(config, &updated, repo_kind)
+            && hours > max_age_hours as i64
+        {
+            // The Release date, unlike the hours since it, is the same on every run
+            let message = if args.reproducible {
+                format!(
+                    "last updated {}, over max_age_hours of {max_age_hours}",
+                    utc_date(updated[&repo_kind], "%Y-%m-%d %H:%M UTC")
+                )
+            } else {
+                format!("updated {hours} hours ago, over max_age_hours of {max_age_hours}")
+            };
+            log::error!("{}: {message}", repo_kind.as_str());
+            repo_errors.push((repo_kind, message));
+        }
+    }
 
-    let mut html = fs::File::create("index.html")?;
-    writeln!(
-        html,
-        "{}",
-        r#"<!DOCTYPE html>
-<html lang='en'>
-<head>
-<meta charset='utf-8'>
-<meta name='viewport' content='width=device-width'>
-<title>Poparazzi</title>
-<script src='https://code.jquery.com/jquery-4.0.0.min.js' integrity='sha256-OaVG6prZf4v69dPg6PhVattBXkcOWQB62pdZ3ORyrao=' crossorigin='anonymous'></script>
-<link rel='stylesheet' type='text/css' href='https://cdn.datatables.net/2.3.7/css/dataTables.dataTables.min.css'>
-<script type='text/javascript' src='https://cdn.datatables.net/2.3.7/js/dataTables.min.js'></script>
-<style>
-td.error {
-    background-color: #800000
+    if !failed_fetches.is_empty() {
+        eprintln!("Failed fetches:");
+        for failed_fetch in failed_fetches.iter() {
+            eprintln!("\t{}: {}", failed_fetch.location(), failed_fetch.error);
+        }
+    }
+
+    Ok(Fetched {
+        apt_infos,
+        repo_errors,
+        repo_warnings,
+        failed_fetches,
+        updated,
+        expired,
+    })
 }
-</style>
-<script type='text/javascript'>
-function onload(){
-    new DataTable('#table', {
-        order: [
-            [0, 'desc'],
-            [1, 'asc'],
-            [2, 'asc']
-        ],
-        paging: false
-    });
+
+// Finds a source in a repo with a binary that Provides a package, so that a virtual package is not
+// reported missing. Provides are only known with --with-binaries.
+fn provider<'a>(
+    apt_infos: &'a AptInfos,
+    repo_kind: RepoKind,
+    codename: Codename,
+    package: &str,
+) -> Option<&'a str> {
+    apt_infos
+        .iter()
+        .find_map(|((source, source_codename), apt_info)| {
+            let version = apt_info.version(repo_kind).as_ref()?;
+            (*source_codename == codename && version.provides.contains(package))
+                .then_some(source.as_str())
+        })
 }
-</script>
-</head>
-<body onload='onload()'>"#
-    )?;
 
-    writeln!(
-        html,
-        "<h4>Generated by <a href='https://github.com/pop-os/poparazzi'>Poparazzi</a> at {}</h4>",
-        encode_text(&format!(
-            "{}",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S %Z")
-        ))
-    )?;
+// Marks packages that are only in Ubuntu, which are only found with --carry-candidates, so they
+// stand out from packages Pop carries
+fn check_carry_candidates(apt_infos: &AptInfos) {
+    for apt_info in apt_infos.values() {
+        let Some(ubuntu) = &apt_info.ubuntu else {
+            continue;
+        };
+        let in_pop = RepoKind::all().into_iter().any(|repo_kind| {
+            repo_kind != RepoKind::Ubuntu && apt_info.version(repo_kind).is_some()
+        });
+        if !in_pop {
+            ubuntu.errors.borrow_mut().push(AptError {
+                severity: Severity::Info,
+                message: "Only in Ubuntu, candidate to carry".to_string(),
+            });
+        }
+    }
+}
 
-    //TODO: why is this required?
-    rustls::crypto::ring::default_provider()
-        .install_default()
-        .expect("Failed to install rustls crypto provider");
-    let token =
-        fs::read_to_string(".github_token").context("Put your Github token in .github_token")?;
-    let token = token.trim();
-    let octocrab = Octocrab::builder().personal_token(token).build()?;
-
-    writeln!(html, "<table width='100%'><tr>")?;
-    for (name, filter) in GITHUB_PR_FILTERS {
-        let filter = format!("{GITHUB_PR_FILTER_BASE} {filter}");
-        let url = format!(
-            "https://github.com/pulls?q={}",
-            urlencoding::encode(&filter)
-        );
-        let page = octocrab
-            .search()
-            .issues_and_pull_requests(&filter)
-            .send()
-            .await?;
-        log::info!("{name}: {}", page.total_count.unwrap_or(0));
-        writeln!(
-            html,
-            "<td><a href='{}'>{}: {}</a></td>",
-            url,
-            encode_text(name),
-            page.total_count.unwrap_or(0)
-        )?;
-        /*TODO: parse PR info?
-        let stream = page
-            .into_stream(&octocrab);
-        pin!(stream);
-        while let Some(pr) = stream.try_next().await? {
-            println!(" - {}: {}", pr.html_url, pr.title);
+// Flags Pop packages with an older version in an extra suite like noble-proposed than in the
+// standard suite, which would be a regression once the extra suite is promoted
+fn check_extra_suites(args: &Args, apt_infos: &AptInfos) {
+    for apt_info in apt_infos.values() {
+        for extra in apt_info.extra_suites.iter() {
+            let Some(version) = apt_info.version(extra.repo_kind) else {
+                continue;
+            };
+            if version::compare_versions(args.upstream_only, &extra.version, &version.version)
+                == std::cmp::Ordering::Less
+            {
+                let message = if args.verbose_errors {
+                    format!(
+                        "{} {} {} older than {} {}",
+                        extra.repo_kind.as_str(),
+                        extra.suite,
+                        extra.version,
+                        version.suite,
+                        version.version
+                    )
+                } else {
+                    format!("Older in {}", extra.suite)
+                };
+                version.errors.borrow_mut().push(AptError {
+                    severity: Severity::Downgrade,
+                    message,
+                });
+            }
         }
-        */
     }
-    writeln!(html, "</tr></table>")?;
+}
 
-    let apt_infos = apt_infos().await?;
-    let mut total_errors = 0;
-    for (_, apt_info) in apt_infos.iter() {
+// Flags Pop packages with an older version in a codename than in its prior codename, which
+// usually means a fix was not forward ported, or a new codename was seeded with older versions
+fn check_codename_order(args: &Args, config: &Config, apt_infos: &AptInfos) {
+    for ((package, codename), apt_info) in apt_infos.iter() {
+        if config.codename_order_exempt(package) {
+            continue;
+        }
+        let Some(prior_info) = config
+            .prior_codename(*codename)
+            .and_then(|prior_codename| apt_infos.get(&(package.clone(), prior_codename)))
+        else {
+            continue;
+        };
         for repo_kind in RepoKind::all() {
-            if let Some(version) = apt_info.version(repo_kind) {
-                total_errors += version.errors.borrow().len();
+            if repo_kind == RepoKind::Ubuntu {
+                continue;
+            }
+            let (Some(version), Some(prior_version)) =
+                (apt_info.version(repo_kind), prior_info.version(repo_kind))
+            else {
+                continue;
+            };
+            if version::compare_versions(
+                args.upstream_only,
+                &version.version,
+                &prior_version.version,
+            ) == std::cmp::Ordering::Less
+            {
+                let message = if args.verbose_errors {
+                    format!(
+                        "{} {} older than {} {}",
+                        codename.as_str(),
+                        version.version,
+                        prior_version.codename.as_str(),
+                        prior_version.version
+                    )
+                } else {
+                    format!("Older than {}", prior_version.codename.as_str())
+                };
+                version.errors.borrow_mut().push(AptError {
+                    severity: Severity::Downgrade,
+                    message,
+                });
             }
         }
     }
+}
+
+// Stable anchor of a package row, like pkg-foo-noble. Package names may contain characters
+// like + that are awkward in URLs, so anything else is hex escaped after an underscore, which
+// cannot appear in package names.
+fn row_id(package: &str, codename: Codename) -> String {
+    let mut id = String::from("pkg-");
+    for c in package.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+            id.push(c);
+        } else {
+            for byte in c.to_string().bytes() {
+                id.push_str(&format!("_{byte:02x}"));
+            }
+        }
+    }
+    id.push('-');
+    id.push_str(codename.as_str());
+    id
+}
+
+// Versions in the Ubuntu development series
+struct UbuntuDevel {
+    // Codename the devel suite currently points to
+    codename: Option<String>,
+    versions: BTreeMap<String, String>,
+}
+
+// Writes a table of packages, either for a single codename or for all codenames with a
+// column for the codename
+fn html_table<W: Write>(
+    html: &mut W,
+    args: &Args,
+    config: &Config,
+    fetched: &Fetched,
+    ubuntu_devel: Option<&UbuntuDevel>,
+    codename_filter: Option<Codename>,
+) -> Result<()> {
+    let Fetched {
+        apt_infos,
+        updated,
+        expired,
+        ..
+    } = fetched;
+    // Ubuntu pockets shown in place of the single Ubuntu column
+    let pockets = if args.split_ubuntu_pockets {
+        config.suite_kinds(RepoKind::Ubuntu)
+    } else {
+        Vec::new()
+    };
+    let rows: Vec<_> = apt_infos
+        .iter()
+        .filter(|((_, codename), _)| codename_filter.is_none_or(|filter| *codename == filter))
+        .collect();
+    let mut total_errors = 0;
+    for (_, apt_info) in rows.iter() {
+        total_errors += apt_info.error_count();
+    }
+    let id = match codename_filter {
+        Some(codename) => format!("table-{}", codename.as_str()),
+        None => "table".to_string(),
+    };
+    // Small tables stay on one page, so searching the page in the browser finds every package
+    let paging = rows.len() > config.html.paging_threshold;
     writeln!(
         html,
-        "<table id='table' class='display compact' style='overflow-wrap: anywhere'>"
+        "<table id='{id}' class='packages display compact' style='overflow-wrap: anywhere' data-paging='{paging}' data-page-length='{}' data-order='{}'>",
+        config.html.page_length,
+        encode_single_quoted_attribute(&serde_json::to_string(&config.html.order)?)
     )?;
     writeln!(html, "<thead>")?;
     writeln!(html, "<tr>")?;
     writeln!(html, "<th>Errors ({})</th>", total_errors)?;
     writeln!(html, "<th>Source</th>")?;
-    writeln!(html, "<th>Codename</th>")?;
+    if codename_filter.is_none() {
+        writeln!(html, "<th>Codename</th>")?;
+    }
     for repo_kind in RepoKind::all() {
+        if repo_kind == RepoKind::Ubuntu && !pockets.is_empty() {
+            for pocket in pockets.iter() {
+                writeln!(
+                    html,
+                    "<th><a href='{}'>{}{}</a></th>",
+                    config.repo_url(repo_kind),
+                    encode_text(repo_kind.as_str()),
+                    encode_text(pocket.as_str())
+                )?;
+            }
+            continue;
+        }
+        // Make an empty column stand out, so it is not mistaken for a repo without errors
+        let has_data = rows
+            .iter()
+            .any(|(_, apt_info)| apt_info.version(repo_kind).is_some());
+        if has_data {
+            // Show how old the repo is against its max_age_hours, if it has one. With
+            // --reproducible, the Release date is shown instead of the hours since it.
+            let (mut class, mut age) = match repo_age(config, updated, repo_kind) {
+                Some((hours, max_age_hours)) => {
+                    let updated = if args.reproducible {
+                        utc_date(updated[&repo_kind], "%Y-%m-%d %H:%M UTC")
+                    } else {
+                        format!("{hours}h ago")
+                    };
+                    if hours > max_age_hours as i64 {
+                        (
+                            format!(" class='{}'", Severity::Critical.as_str()),
+                            format!("<br/>Updated {updated}, over {max_age_hours}h"),
+                        )
+                    } else {
+                        (
+                            String::new(),
+                            format!("<br/><small>Updated {updated}</small>"),
+                        )
+                    }
+                }
+                None => (String::new(), String::new()),
+            };
+            if let Some(suites) = expired.get(&repo_kind) {
+                class = format!(" class='{}'", Severity::Critical.as_str());
+                age.push_str(&format!(
+                    "<br/>Expired: {}",
+                    encode_text(&suites.join(", "))
+                ));
+            }
+            writeln!(
+                html,
+                "<th{class}><a href='{}'>{}</a>{age}</th>",
+                config.repo_url(repo_kind),
+                encode_text(repo_kind.as_str())
+            )?;
+        } else {
+            writeln!(
+                html,
+                "<th class='missing' title='No packages were configured or fetched'><a href='{}'>{}</a><br/>No data</th>",
+                config.repo_url(repo_kind),
+                encode_text(repo_kind.as_str())
+            )?;
+        }
+    }
+    if let Some(ubuntu_devel) = ubuntu_devel {
+        let suite = match &ubuntu_devel.codename {
+            Some(codename) => format!("{}: {codename}", config.ubuntu_devel_suite),
+            None => config.ubuntu_devel_suite.clone(),
+        };
         writeln!(
             html,
-            "<th><a href='{}'>{}</a></th>",
-            repo_kind.url(),
-            encode_text(repo_kind.as_str())
+            "<th>{} ({})</th>",
+            encode_text(RepoKind::Ubuntu.as_str()),
+            encode_text(&suite)
         )?;
     }
+    writeln!(html, "<th>Tracking</th>")?;
     writeln!(html, "</tr>")?;
     writeln!(html, "</thead>")?;
     writeln!(html, "<tbody>")?;
-    for ((package, codename), apt_info) in apt_infos.iter() {
-        writeln!(html, "<tr>")?;
-        let mut errors = 0;
-        for repo_kind in RepoKind::all() {
-            if let Some(version) = apt_info.version(repo_kind) {
-                errors += version.errors.borrow().len();
-            }
+    for ((package, codename), apt_info) in rows {
+        writeln!(html, "<tr id='{}'>", row_id(package, *codename))?;
+        let errors = apt_info.error_count();
+        match apt_info.severity() {
+            Some(severity) => writeln!(html, "<td class='{}'>{}</td>", severity.as_str(), errors)?,
+            None => writeln!(html, "<td>{}</td>", errors)?,
         }
-        if errors > 0 {
-            writeln!(html, "<td class='error'>{}</td>", errors)?;
-        } else {
-            writeln!(html, "<td>{}</td>", errors)?;
+        match apt_info.section() {
+            Some(section) => writeln!(
+                html,
+                "<td title='{}'>{}</td>",
+                encode_single_quoted_attribute(section),
+                encode_text(&package)
+            )?,
+            None => writeln!(html, "<td>{}</td>", encode_text(&package))?,
+        }
+        if codename_filter.is_none() {
+            writeln!(html, "<td>{}</td>", encode_text(codename.as_str()))?;
         }
-        writeln!(html, "<td>{}</td>", encode_text(&package))?;
-        writeln!(html, "<td>{}</td>", encode_text(codename.as_str()))?;
         for repo_kind in RepoKind::all() {
+            if repo_kind == RepoKind::Ubuntu && !pockets.is_empty() {
+                for pocket in pockets.iter() {
+                    match apt_info.ubuntu_pockets.get(pocket.as_str()) {
+                        Some(version) => version.html_cell(html, args, config, package)?,
+                        None => writeln!(html, "<td>None</td>",)?,
+                    }
+                }
+                continue;
+            }
             if let Some(version) = apt_info.version(repo_kind) {
-                version.html_cell(&mut html, package)?;
+                version.html_cell(html, args, config, package)?;
+            } else if apt_info.missing.contains(&repo_kind) {
+                writeln!(
+                    html,
+                    "<td class='missing'>None<br/>Expected in {}</td>",
+                    encode_text(repo_kind.as_str())
+                )?;
             } else {
                 writeln!(html, "<td>None</td>",)?;
             }
         }
+        if let Some(ubuntu_devel) = ubuntu_devel {
+            match ubuntu_devel.versions.get(package) {
+                Some(version) => {
+                    // Not counted as an error, it only informs when to rebase
+                    let ahead = apt_info.release.as_ref().is_some_and(|release| {
+                        version::compare_versions(args.upstream_only, version, &release.version)
+                            == std::cmp::Ordering::Greater
+                    });
+                    if ahead {
+                        writeln!(
+                            html,
+                            "<td class='{}'>{}<br/>Newer than {}</td>",
+                            Severity::Info.as_str(),
+                            encode_text(version),
+                            encode_text(RepoKind::Release.as_str())
+                        )?;
+                    } else {
+                        writeln!(html, "<td>{}</td>", encode_text(version))?;
+                    }
+                }
+                None => writeln!(html, "<td>None</td>")?,
+            }
+        }
+        match config.tracking_url(package) {
+            Some(url) => writeln!(
+                html,
+                "<td><a href='{}'>Issue</a></td>",
+                encode_single_quoted_attribute(url.as_str())
+            )?,
+            None => writeln!(html, "<td></td>")?,
+        }
         writeln!(html, "</tr>")?;
     }
     writeln!(html, "</tbody>")?;
     writeln!(html, "</table>")?;
+    Ok(())
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html lang='en'>
+<head>
+<meta charset='utf-8'>
+<meta name='viewport' content='width=device-width'>
+<script src='https://code.jquery.com/jquery-4.0.0.min.js' integrity='sha256-OaVG6prZf4v69dPg6PhVattBXkcOWQB62pdZ3ORyrao=' crossorigin='anonymous'></script>
+<link rel='stylesheet' type='text/css' href='https://cdn.datatables.net/2.3.7/css/dataTables.dataTables.min.css'>
+<script type='text/javascript' src='https://cdn.datatables.net/2.3.7/js/dataTables.min.js'></script>
+<script type='text/javascript'>
+function onload(){
+    // Paging and order are set by data attributes on each table, from the html config
+    $('table.packages').each(function() {
+        var table = new DataTable(this);
+        // Turn to the page of a linked row, so it can be scrolled to
+        if (location.hash && $(this).data('paging')) {
+            var row = table.row(location.hash);
+            if (row.any()) {
+                var index = table.rows({order: 'applied', search: 'applied'}).indexes().indexOf(row.index());
+                table.page(Math.floor(index / table.page.len())).draw(false);
+            }
+        }
+    });
+    // Tables are redrawn after the browser jumps to the anchor, so jump again
+    if (location.hash) {
+        var row = document.getElementById(location.hash.substring(1));
+        if (row) {
+            row.scrollIntoView({block: 'center'});
+        }
+    }
+}
+</script>"#;
+
+// Dependencies enable both the ring and aws-lc-rs backends of rustls, so it cannot pick a
+// default and panics when a TLS client is built unless one is installed first. Installing
+// fails if a provider is already installed, which is fine, so this is safe to call repeatedly.
+fn install_crypto_provider() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        if rustls::crypto::ring::default_provider()
+            .install_default()
+            .is_err()
+        {
+            log::debug!("rustls crypto provider already installed");
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args = Args::parse();
+    // Flushes the profile when main returns, including on errors
+    let _profile = args.profile.as_deref().map(profile::start).transpose()?;
+    let mut config = Config::load(&args.configs, &args.overrides)?;
+    if let Some(title) = &args.title {
+        config.title = title.clone();
+    }
+    if let Some(environment) = &args.environment {
+        config.environment = Some(environment.clone());
+    }
+
+    install_crypto_provider();
+    apt::set_retry_budget(config.retry_budget);
+
+    let res = match &args.command {
+        Some(Command::ForwardPorts) => forward_ports(&args, &config).await,
+        Some(Command::Compare {
+            repo_a,
+            repo_b,
+            codename,
+            json,
+        }) => {
+            compare::compare(
+                &config,
+                *repo_a,
+                *repo_b,
+                *codename,
+                *json,
+                args.upstream_only,
+            )
+            .await
+        }
+        Some(Command::Missing {
+            repo_a,
+            repo_b,
+            codename,
+            json,
+        }) => compare::missing(&config, *repo_a, *repo_b, *codename, *json).await,
+        Some(Command::Status) => status::status(&config).await,
+        Some(Command::ListRepos) => {
+            repos::list_repos(&config);
+            Ok(())
+        }
+        Some(Command::Explain { package, codename }) => {
+            explain::explain(&args, &config, package, *codename).await
+        }
+        Some(Command::Versions { package }) => versions::versions(&args, &config, package).await,
+        Some(Command::Maintainers { json }) => {
+            maintainers::maintainers(&args, &config, *json).await
+        }
+        Some(Command::ErrorClusters { json }) => clusters::clusters(&args, &config, *json).await,
+        Some(Command::Diff { other, json }) => diff::diff(&args, &config, other, *json).await,
+        Some(Command::Graph) => {
+            graph::graph();
+            Ok(())
+        }
+        None => match args.format {
+            Format::Html => report(&args, &config).await,
+            Format::Text => report_text(&args, &config).await,
+            Format::Jsonl => report_jsonl(&args, &config).await,
+            Format::SnapshotTxt => report_snapshot(&args, &config).await,
+        },
+    };
+
+    let (wire_bytes, decoded_bytes) = apt::downloaded_bytes();
+    log::info!(
+        "downloaded {:.1} MiB of indices, {:.1} MiB decompressed",
+        wire_bytes as f64 / 1048576.0,
+        decoded_bytes as f64 / 1048576.0
+    );
+
+    res
+}
+
+// Lists packages in Release for a codename that are missing from Staging for the next codename
+async fn forward_ports(args: &Args, config: &Config) -> Result<()> {
+    let apt_infos = load_apt_infos(args, config).await?.apt_infos;
+    for ((package, codename), apt_info) in apt_infos.iter() {
+        let Some(release) = &apt_info.release else {
+            continue;
+        };
+        let Some(next_codename) = codename.next() else {
+            continue;
+        };
+        let next_staging = apt_infos
+            .get(&(package.clone(), next_codename))
+            .and_then(|next_info| next_info.staging.as_ref());
+        if next_staging.is_none() {
+            println!(
+                "{package} {} in {codename} {} is not in {next_codename} {}",
+                release.version,
+                RepoKind::Release.as_str(),
+                RepoKind::Staging.as_str()
+            );
+        }
+    }
+    Ok(())
+}
+
+// Loads package data and errors from --from, or fetches them and saves them to --save-model
+async fn load_apt_infos(args: &Args, config: &Config) -> Result<Fetched> {
+    if let Some(path) = &args.from {
+        return model::load(path);
+    }
+    let fetched = apt_infos(args, config).await?;
+    if let Some(path) = &args.save_model {
+        model::save(path, &fetched)?;
+    }
+    Ok(fetched)
+}
+
+// Loads package data, keeping only the requested section and codename if any
+async fn filtered_apt_infos(args: &Args, config: &Config) -> Result<Fetched> {
+    let mut fetched = load_apt_infos(args, config).await?;
+    if let Some(section) = &args.section {
+        fetched
+            .apt_infos
+            .retain(|_, apt_info| apt_info.section() == Some(section.as_str()));
+    }
+    if let Some(codename) = args.codename {
+        fetched
+            .apt_infos
+            .retain(|(_, package_codename), _| *package_codename == codename);
+    }
+    Ok(fetched)
+}
+
+// Largest error clusters listed after the error counts, and the size a cluster needs to be listed
+const SUMMARY_CLUSTERS: usize = 5;
+const SUMMARY_CLUSTER_MIN_ERRORS: usize = 10;
+
+// Error counts of a run, logged at the end and sent to StatsD
+pub struct ErrorCounts {
+    // Errors that decide --max-errors, including warnings with --warnings-as-errors
+    total: usize,
+    // Problems with a repo as a whole, left out with --codename
+    repo_errors: usize,
+    // Errors on packages and repos by repo, including missing expected packages
+    repos: BTreeMap<RepoKind, usize>,
+    // Errors on packages by codename
+    codenames: BTreeMap<Codename, usize>,
+    // Repo warnings and failed fetches
+    warnings: usize,
+}
+
+impl ErrorCounts {
+    fn new(args: &Args, fetched: &Fetched) -> Self {
+        let mut codenames = BTreeMap::<Codename, usize>::new();
+        let mut repos: BTreeMap<RepoKind, usize> = RepoKind::all()
+            .into_iter()
+            .map(|repo_kind| (repo_kind, 0))
+            .collect();
+        for ((_, codename), apt_info) in fetched.apt_infos.iter() {
+            *codenames.entry(*codename).or_default() += apt_info.error_count();
+            for repo_kind in RepoKind::all() {
+                if let Some(version) = apt_info.version(repo_kind) {
+                    *repos.entry(repo_kind).or_default() += version.errors.borrow().len();
+                }
+            }
+            for repo_kind in apt_info.missing.iter() {
+                *repos.entry(*repo_kind).or_default() += 1;
+            }
+        }
+        let repo_errors = match args.codename {
+            Some(_) => 0,
+            None => {
+                for (repo_kind, _error) in fetched.repo_errors.iter() {
+                    *repos.entry(*repo_kind).or_default() += 1;
+                }
+                fetched.repo_errors.len()
+            }
+        };
+        // Failed fetches of suite aliases have no codename, so they are left out with --codename
+        let warnings = match args.codename {
+            Some(codename) => fetched
+                .failed_fetches
+                .iter()
+                .filter(|failed_fetch| failed_fetch.codename == Some(codename))
+                .count(),
+            None => fetched.repo_warnings.len() + fetched.failed_fetches.len(),
+        };
+        let mut total = repo_errors + codenames.values().sum::<usize>();
+        if args.warnings_as_errors {
+            total += warnings;
+        }
+        Self {
+            total,
+            repo_errors,
+            repos,
+            codenames,
+            warnings,
+        }
+    }
+}
+
+// Logs the error count of each codename, and fails if there are more than --max-errors, or any
+// warnings with --warnings-as-errors alone. Repo errors belong to no codename, so they are left
+// out when the run is scoped with --codename.
+async fn check_errors(args: &Args, config: &Config, fetched: &Fetched) -> Result<()> {
+    let error_counts = ErrorCounts::new(args, fetched);
+    let ErrorCounts {
+        total: total_errors,
+        repo_errors,
+        warnings,
+        ..
+    } = error_counts;
+
+    let mut counts = Vec::new();
+    if repo_errors > 0 {
+        counts.push(format!("repos {repo_errors}"));
+    }
+    for (codename, errors) in error_counts.codenames.iter() {
+        counts.push(format!("{} {errors}", codename.as_str()));
+    }
+    if args.warnings_as_errors && warnings > 0 {
+        counts.push(format!("warnings {warnings}"));
+    }
+    if total_errors > 0 {
+        log::warn!("finished with {total_errors} errors: {}", counts.join(", "));
+    } else {
+        log::info!("finished without errors");
+    }
+    if !args.warnings_as_errors && warnings > 0 {
+        log::warn!("{warnings} warnings, not counted as errors without --warnings-as-errors");
+    }
+    statsd::send(config, &error_counts).await;
+    // Hundreds of errors from one broken component read better as one line pointing at it
+    for cluster in clusters::error_clusters(&fetched.apt_infos)
+        .iter()
+        .take_while(|cluster| cluster.errors >= SUMMARY_CLUSTER_MIN_ERRORS)
+        .take(SUMMARY_CLUSTERS)
+    {
+        log::warn!(
+            "{} {} errors in {}, like {:?}",
+            cluster.errors,
+            cluster.severity.as_str(),
+            cluster.location(),
+            cluster.example
+        );
+    }
+
+    if let Some(max_errors) = args.max_errors
+        && total_errors > max_errors
+    {
+        return Err(anyhow!(
+            "{total_errors} errors is over --max-errors {max_errors}"
+        ));
+    }
+    // Without a limit, any warning fails the run so that it is not only counted
+    if args.max_errors.is_none() && args.warnings_as_errors && warnings > 0 {
+        return Err(anyhow!("{warnings} warnings with --warnings-as-errors"));
+    }
+    Ok(())
+}
+
+// Path the report is written to, or None for stdout
+fn output_path(args: &Args, default: &str) -> Option<PathBuf> {
+    match &args.output {
+        Some(path) if path.as_os_str() == "-" => None,
+        Some(path) => Some(path.clone()),
+        None if args.compress => Some(PathBuf::from(format!("{default}.gz"))),
+        None => Some(PathBuf::from(default)),
+    }
+}
+
+// Where a report is written, which must be finished to write the end of a gzip stream and to
+// catch errors that dropping would hide
+enum Output {
+    Plain(Box<dyn Write>),
+    Gzip(GzEncoder<Box<dyn Write>>),
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl Output {
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Plain(mut writer) => writer.flush()?,
+            Self::Gzip(encoder) => encoder.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+fn create_output(args: &Args, path: Option<&PathBuf>) -> Result<Output> {
+    let writer: Box<dyn Write> = match path {
+        Some(path) => Box::new(io::BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        )),
+        None => Box::new(io::BufWriter::new(io::stdout())),
+    };
+    Ok(if args.compress {
+        Output::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+    } else {
+        Output::Plain(writer)
+    })
+}
+
+// Generates index.txt
+async fn report_text(args: &Args, config: &Config) -> Result<()> {
+    let fetched = filtered_apt_infos(args, config).await?;
+    let mut text = create_output(args, output_path(args, "index.txt").as_ref())?;
+    text::text_report(
+        &mut text,
+        config,
+        &fetched.apt_infos,
+        &fetched.repo_errors,
+        &fetched.repo_warnings,
+        &fetched.failed_fetches,
+    )?;
+    text.finish()?;
+    check_errors(args, config, &fetched).await
+}
+
+// Generates index.jsonl
+async fn report_jsonl(args: &Args, config: &Config) -> Result<()> {
+    let fetched = filtered_apt_infos(args, config).await?;
+    let Fetched {
+        apt_infos,
+        repo_errors,
+        failed_fetches,
+        ..
+    } = &fetched;
+    if !repo_errors.is_empty() {
+        log::warn!(
+            "{} repo errors are not included in JSON Lines",
+            repo_errors.len()
+        );
+    }
+    if !failed_fetches.is_empty() {
+        log::warn!(
+            "{} failed fetches are not included in JSON Lines, use --save-model to keep them",
+            failed_fetches.len()
+        );
+    }
+    let mut jsonl = create_output(args, output_path(args, "index.jsonl").as_ref())?;
+    jsonl::jsonl_report(&mut jsonl, config, apt_infos)?;
+    jsonl.finish()?;
+    check_errors(args, config, &fetched).await
+}
+
+// Generates snapshot.txt
+async fn report_snapshot(args: &Args, config: &Config) -> Result<()> {
+    let fetched = filtered_apt_infos(args, config).await?;
+    let mut snapshot = create_output(args, output_path(args, "snapshot.txt").as_ref())?;
+    snapshot::snapshot_report(&mut snapshot, &fetched.apt_infos)?;
+    snapshot.finish()?;
+    check_errors(args, config, &fetched).await
+}
+
+// Writes the oldest PR, review decisions, and requested teams of a PR filter. The oldest PR is
+// shown by date with --reproducible, like other ages.
+fn pr_details_html<W: Write>(
+    html: &mut W,
+    args: &Args,
+    count: u64,
+    details: &github::PrDetails,
+) -> Result<()> {
+    if let Some(oldest) = details.oldest {
+        let opened = if args.reproducible {
+            utc_date(oldest, "%Y-%m-%d")
+        } else {
+            format!(
+                "{} days ago",
+                (chrono::Utc::now().timestamp() - oldest) / 86400
+            )
+        };
+        writeln!(html, "<br/><small>Oldest opened {opened}</small>")?;
+    }
+    let counted = |counts: &BTreeMap<String, u64>| {
+        counts
+            .iter()
+            .map(|(name, count)| format!("{} {count}", name.to_lowercase().replace('_', " ")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !details.decisions.is_empty() {
+        writeln!(
+            html,
+            "<br/><small>{}</small>",
+            encode_text(&counted(&details.decisions))
+        )?;
+    }
+    if !details.teams.is_empty() {
+        writeln!(
+            html,
+            "<br/><small>Requested: {}</small>",
+            encode_text(&counted(&details.teams))
+        )?;
+    }
+    if count > github::PR_DETAILS_LIMIT {
+        writeln!(
+            html,
+            "<br/><small>Of the oldest {}</small>",
+            github::PR_DETAILS_LIMIT
+        )?;
+    }
+    Ok(())
+}
+
+// Builds a GitHub client from the token file, what it is needed for is named in errors
+fn github_client(config: &Config, purpose: &str) -> Result<Octocrab> {
+    if !apt::host_allowed(&config.allowed_hosts, Some(GITHUB_API_HOST)) {
+        return Err(anyhow!(
+            "{GITHUB_API_HOST} is not in allowed_hosts, needed for {purpose}"
+        ));
+    }
+    let token = fs::read_to_string(&config.github_token)
+        .with_context(|| format!("Put your Github token in {}", config.github_token.display()))?;
+    Ok(Octocrab::builder().personal_token(token.trim()).build()?)
+}
+
+// Sets the date of the GitHub commit each Pop version was built from. Dates never change, so
+// they are kept in commit_date_cache and only new commits are looked up. With --from nothing is
+// looked up, and only cached dates are shown.
+async fn add_commit_dates(args: &Args, config: &Config, apt_infos: &mut AptInfos) -> Result<()> {
+    let mut dates = github::load_commit_dates(&config.commit_date_cache);
+    if args.from.is_none() && args.github_snapshot.is_none() {
+        let mut commits = BTreeSet::new();
+        for apt_info in apt_infos.values() {
+            for repo_kind in COMMIT_REPO_KINDS {
+                if let Some((repo, commit)) = apt_info
+                    .version(repo_kind)
+                    .as_ref()
+                    .and_then(|version| version.github_repo_commit())
+                {
+                    commits.insert((repo.to_string(), commit.to_string()));
+                }
+            }
+        }
+        if commits
+            .iter()
+            .any(|(repo, commit)| !dates.contains(repo, commit))
+        {
+            let octocrab = github_client(config, "--commit-dates")?;
+            github::lookup_commit_dates(&octocrab, &mut dates, commits).await;
+            github::save_commit_dates(&config.commit_date_cache, &dates)?;
+        }
+    }
+    for apt_info in apt_infos.values_mut() {
+        for repo_kind in COMMIT_REPO_KINDS {
+            let Some(version) = apt_info.version_mut(repo_kind) else {
+                continue;
+            };
+            version.commit_date = version
+                .github_repo_commit()
+                .and_then(|(repo, commit)| dates.get(repo, commit));
+        }
+    }
+    Ok(())
+}
+
+// Generates index.html
+async fn report(args: &Args, config: &Config) -> Result<()> {
+    let path = output_path(args, "index.html");
+    let mut html = create_output(args, path.as_ref())?;
+    writeln!(html, "{HTML_HEAD}")?;
+    writeln!(
+        html,
+        "<title>{}</title>",
+        encode_text(&config.report_title())
+    )?;
+    writeln!(html, "<style>")?;
+    for (class, color) in [
+        (Severity::Info.as_str(), &config.palette.info),
+        (Severity::Warning.as_str(), &config.palette.warning),
+        (Severity::Critical.as_str(), &config.palette.critical),
+        (Severity::Downgrade.as_str(), &config.palette.downgrade),
+        (Severity::Target.as_str(), &config.palette.target),
+        (Severity::Conflict.as_str(), &config.palette.conflict),
+        (Severity::Dangling.as_str(), &config.palette.dangling),
+        (Severity::Unbuilt.as_str(), &config.palette.unbuilt),
+        ("missing", &config.palette.missing),
+    ] {
+        writeln!(
+            html,
+            "td.{class}, th.{class}, li.{class} {{\n    background-color: {color}\n}}"
+        )?;
+    }
+    writeln!(html, "tr:target {{\n    outline: 3px solid #2080c0\n}}")?;
+    writeln!(html, "</style>")?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body onload='onload()'>")?;
+    if let Some(environment) = &config.environment {
+        writeln!(
+            html,
+            "<h2 style='background-color: {}'>{}: {}</h2>",
+            config.palette.banner,
+            encode_text(&config.title),
+            encode_text(environment)
+        )?;
+    }
+
+    let timestamp = chrono::Local::now()
+        .format("%Y-%m-%d %H:%M:%S %Z")
+        .to_string();
+    if args.reproducible {
+        // Keep the timestamp out of the report so it only changes when the data does
+        match &path {
+            Some(path) => {
+                let mut timestamp_path = path.clone().into_os_string();
+                timestamp_path.push(".timestamp");
+                fs::write(timestamp_path, format!("{timestamp}\n"))?;
+            }
+            None => log::info!("generated at {timestamp}"),
+        }
+        writeln!(
+            html,
+            "<h4>Generated by <a href='https://github.com/pop-os/poparazzi'>Poparazzi</a></h4>",
+        )?;
+    } else {
+        writeln!(
+            html,
+            "<h4>Generated by <a href='https://github.com/pop-os/poparazzi'>Poparazzi</a> at {}</h4>",
+            encode_text(&timestamp)
+        )?;
+    }
+
+    let mut snapshot_timestamp = None;
+    let cached_pr_counts = if let Some(snapshot) = &args.github_snapshot {
+        let (timestamp, pr_counts) = github::snapshot_pr_counts(snapshot)?;
+        snapshot_timestamp = Some(timestamp);
+        Some(pr_counts)
+    } else if args.from.is_some() {
+        // Rendering from a saved model makes no requests
+        Some(Vec::new())
+    } else if args.refresh_github || config.github_cache_ttl == 0 {
+        None
+    } else {
+        github::cached_pr_counts(&config.github_cache, config.github_cache_ttl)
+    };
+    let mut pr_details = Vec::new();
+    let pr_counts = match cached_pr_counts {
+        Some(pr_counts) => pr_counts,
+        None => {
+            let octocrab = github_client(config, "GitHub PR counts")?;
+            let pr_counts = if args.github_graphql {
+                let (pr_counts, details) = github::pr_counts_graphql(&octocrab)
+                    .await?
+                    .into_iter()
+                    .unzip();
+                pr_details = details;
+                pr_counts
+            } else {
+                github::pr_counts(&octocrab).await?
+            };
+            if config.github_cache_ttl > 0 {
+                github::save_pr_counts(&config.github_cache, &pr_counts)?;
+            }
+            pr_counts
+        }
+    };
+    if !pr_counts.is_empty() {
+        writeln!(html, "<table width='100%'><tr>")?;
+        for (i, ((name, filter), count)) in GITHUB_PR_FILTERS.iter().zip(pr_counts).enumerate() {
+            log::info!("{name}: {count}");
+            writeln!(
+                html,
+                "<td><a href='{}'>{}: {}</a>",
+                github::pr_url(filter),
+                encode_text(name),
+                count
+            )?;
+            if let Some(details) = pr_details.get(i) {
+                pr_details_html(&mut html, args, count, details)?;
+            }
+            writeln!(html, "</td>")?;
+        }
+        writeln!(html, "</tr></table>")?;
+    }
+    if let Some(timestamp) = snapshot_timestamp {
+        let taken = chrono::DateTime::from_timestamp(timestamp, 0)
+            .map_or_else(|| timestamp.to_string(), |taken| taken.to_rfc3339());
+        writeln!(
+            html,
+            "<p>PR counts from a snapshot taken at {}</p>",
+            encode_text(&taken)
+        )?;
+    }
+
+    let mut fetched = filtered_apt_infos(args, config).await?;
+    if args.commit_dates {
+        add_commit_dates(args, config, &mut fetched.apt_infos).await?;
+    }
+    let Fetched {
+        apt_infos,
+        repo_errors,
+        repo_warnings,
+        failed_fetches,
+        ..
+    } = &fetched;
+    let ubuntu_devel = if args.ubuntu_devel && args.from.is_none() {
+        let repo = AptRepo::new(config, RepoKind::Ubuntu)?;
+        let mut versions = BTreeMap::new();
+        let codename =
+            compare::suite_versions(&repo, &config.ubuntu_devel_suite, &mut versions).await?;
+        Some(UbuntuDevel { codename, versions })
+    } else {
+        None
+    };
+    if !failed_fetches.is_empty() {
+        writeln!(html, "<h3>Failed fetches ({})</h3>", failed_fetches.len())?;
+        writeln!(html, "<ul>")?;
+        for failed_fetch in failed_fetches.iter() {
+            writeln!(
+                html,
+                "<li class='{}'>{}: {}</li>",
+                Severity::Critical.as_str(),
+                encode_text(&failed_fetch.location()),
+                encode_text(&failed_fetch.error)
+            )?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+    if !repo_errors.is_empty() {
+        writeln!(html, "<h3>Repo errors ({})</h3>", repo_errors.len())?;
+        writeln!(html, "<ul>")?;
+        for (repo_kind, message) in repo_errors.iter() {
+            writeln!(
+                html,
+                "<li class='{}'>{}: {}</li>",
+                Severity::Critical.as_str(),
+                encode_text(repo_kind.as_str()),
+                encode_text(message)
+            )?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+    if !repo_warnings.is_empty() {
+        writeln!(html, "<h3>Repo warnings ({})</h3>", repo_warnings.len())?;
+        writeln!(html, "<ul>")?;
+        for (repo_kind, message) in repo_warnings.iter() {
+            writeln!(
+                html,
+                "<li class='{}'>{}: {}</li>",
+                Severity::Warning.as_str(),
+                encode_text(repo_kind.as_str()),
+                encode_text(message)
+            )?;
+        }
+        writeln!(html, "</ul>")?;
+    }
+    if args.split_codenames {
+        let mut codenames: Vec<Codename> =
+            apt_infos.keys().map(|(_, codename)| *codename).collect();
+        codenames.sort();
+        codenames.dedup();
+        for codename in codenames {
+            writeln!(
+                html,
+                "<h3 id='{}'>{}</h3>",
+                codename.as_str(),
+                encode_text(codename.as_str())
+            )?;
+            html_table(
+                &mut html,
+                args,
+                config,
+                &fetched,
+                ubuntu_devel.as_ref(),
+                Some(codename),
+            )?;
+        }
+    } else {
+        html_table(
+            &mut html,
+            args,
+            config,
+            &fetched,
+            ubuntu_devel.as_ref(),
+            None,
+        )?;
+    }
 
     writeln!(
         html,
         r#"</body>
 </html>"#
     )?;
+    html.finish()?;
+    check_errors(args, config, &fetched).await
+}
 
-    if total_errors > 0 {
-        log::warn!("finished with {} errors", total_errors);
-    } else {
-        log::info!("finished without errors");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    // Package and version of each source, by component
+    type Components = BTreeMap<String, Vec<(String, String)>>;
+
+    // Synthetic repos served like mirrors, each below its config id as in /release/. Suites
+    // without sources are not served, so their Release is a failed fetch that --keep-going
+    // skips.
+    #[derive(Default)]
+    struct Fixture {
+        sources: BTreeMap<(RepoKind, String), Components>,
+        // Extra fields of a Release, like Valid-Until
+        release_fields: BTreeMap<(RepoKind, String), Vec<String>>,
+        // Stanzas of the amd64 Packages index, by repo, suite, and component
+        binaries: BTreeMap<(RepoKind, String, String), Vec<String>>,
+        // Config values, as with --set
+        overrides: Vec<String>,
     }
 
-    Ok(())
+    impl Fixture {
+        fn source(
+            mut self,
+            repo_kind: RepoKind,
+            suite: &str,
+            component: &str,
+            package: &str,
+            version: &str,
+        ) -> Self {
+            self.sources
+                .entry((repo_kind, suite.to_string()))
+                .or_default()
+                .entry(component.to_string())
+                .or_default()
+                .push((package.to_string(), version.to_string()));
+            self
+        }
+
+        fn release_field(
+            mut self,
+            repo_kind: RepoKind,
+            suite: &str,
+            key: &str,
+            value: &str,
+        ) -> Self {
+            self.release_fields
+                .entry((repo_kind, suite.to_string()))
+                .or_default()
+                .push(format!("{key}: {value}\n"));
+            self
+        }
+
+        fn binary(
+            mut self,
+            repo_kind: RepoKind,
+            suite: &str,
+            component: &str,
+            stanza: &str,
+        ) -> Self {
+            self.binaries
+                .entry((repo_kind, suite.to_string(), component.to_string()))
+                .or_default()
+                .push(stanza.to_string());
+            self
+        }
+
+        fn set(mut self, assignment: &str) -> Self {
+            self.overrides.push(assignment.to_string());
+            self
+        }
+
+        async fn fetch(&self, extra_args: &[&str]) -> Fetched {
+            let mut routes = Vec::new();
+            for ((repo_kind, suite), components) in self.sources.iter() {
+                let dists = format!("/{}/dists/{suite}", repo_kind.id());
+                let codename = suite.split('-').next().unwrap();
+                let component_names: Vec<_> = components.keys().map(|x| x.as_str()).collect();
+                let mut release = format!(
+                    "Codename: {codename}\nComponents: {}\nArchitectures: amd64\n",
+                    component_names.join(" ")
+                );
+                if let Some(fields) = self.release_fields.get(&(*repo_kind, suite.clone())) {
+                    release.push_str(&fields.concat());
+                }
+                routes.push((format!("{dists}/Release"), release));
+                for (component, sources) in components.iter() {
+                    let index: Vec<_> = sources
+                        .iter()
+                        .map(|(package, version)| {
+                            format!("Package: {package}\nVersion: {version}\n")
+                        })
+                        .collect();
+                    routes.push((
+                        format!("{dists}/{component}/source/Sources"),
+                        index.join("\n"),
+                    ));
+                    let key = (*repo_kind, suite.clone(), component.clone());
+                    if let Some(stanzas) = self.binaries.get(&key) {
+                        routes.push((
+                            format!("{dists}/{component}/binary-amd64/Packages"),
+                            stanzas.join("\n"),
+                        ));
+                    }
+                }
+            }
+            let server = TestServer::start(
+                routes
+                    .iter()
+                    .map(|(path, body)| (path.as_str(), vec![Response::ok(body.as_str())]))
+                    .collect(),
+            )
+            .await;
+            let overrides: Vec<_> = RepoKind::all()
+                .into_iter()
+                .map(|repo_kind| format!("repos.{0}.url={1}{0}/", repo_kind.id(), server.url))
+                .chain(self.overrides.iter().cloned())
+                .collect();
+            let config = Config::load(&[] as &[&str], &overrides).unwrap();
+            apt_infos(&args(extra_args), &config).await.unwrap()
+        }
+    }
+
+    fn args(extra_args: &[&str]) -> Args {
+        Args::parse_from(["poparazzi", "--keep-going"].iter().chain(extra_args))
+    }
+
+    fn version<'a>(
+        fetched: &'a Fetched,
+        package: &str,
+        codename: Codename,
+        repo_kind: RepoKind,
+    ) -> &'a AptVersion {
+        fetched.apt_infos[&(package.to_string(), codename)]
+            .version(repo_kind)
+            .as_ref()
+            .unwrap()
+    }
+
+    fn errors(version: &AptVersion) -> Vec<(Severity, String)> {
+        version
+            .errors
+            .borrow()
+            .iter()
+            .map(|err| (err.severity, err.message.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn ubuntu_keeps_highest_version_of_all_pockets() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.2")
+            .source(RepoKind::Ubuntu, "noble", "main", "foo", "1.0")
+            .source(RepoKind::Ubuntu, "noble-updates", "main", "foo", "1.1")
+            .source(RepoKind::Ubuntu, "noble-security", "main", "foo", "1.0.1")
+            .source(RepoKind::Ubuntu, "noble", "main", "bar", "2.0")
+            .fetch(&[])
+            .await;
+        let ubuntu = version(&fetched, "foo", Codename::Noble, RepoKind::Ubuntu);
+        assert_eq!(ubuntu.version, "1.1");
+        assert_eq!(ubuntu.suite, "noble-updates");
+        assert_eq!(
+            fetched.apt_infos[&("foo".to_string(), Codename::Noble)]
+                .ubuntu_pockets
+                .len(),
+            3
+        );
+        assert_eq!(
+            errors(version(&fetched, "foo", Codename::Noble, RepoKind::Release)),
+            [(Severity::Warning, "Not in Staging".to_string())]
+        );
+        // Ubuntu packages that Pop does not have are left out
+        assert!(
+            !fetched
+                .apt_infos
+                .contains_key(&("bar".to_string(), Codename::Noble))
+        );
+    }
+
+    #[tokio::test]
+    async fn older_version_than_a_prior_repo_is_an_error() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.1")
+            .source(RepoKind::Staging, "noble", "main", "foo", "1.0")
+            .source(RepoKind::Ubuntu, "noble", "main", "foo", "1.2")
+            .fetch(&[])
+            .await;
+        assert_eq!(
+            errors(version(&fetched, "foo", Codename::Noble, RepoKind::Staging)),
+            [
+                (Severity::Critical, "Older than Release".to_string()),
+                (Severity::Info, "Older than Ubuntu".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors(version(&fetched, "foo", Codename::Noble, RepoKind::Release)),
+            [(Severity::Info, "Older than Ubuntu".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn version_missing_from_a_later_repo_is_a_warning() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.0")
+            .source(RepoKind::Staging, "noble", "main", "bar", "1.0")
+            .fetch(&[])
+            .await;
+        assert_eq!(
+            errors(version(&fetched, "foo", Codename::Noble, RepoKind::Release)),
+            [(Severity::Warning, "Not in Staging".to_string())]
+        );
+        assert!(errors(version(&fetched, "bar", Codename::Noble, RepoKind::Staging)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn versions_differing_across_components_are_a_conflict() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Staging, "noble", "main", "foo", "1.0")
+            .source(RepoKind::Staging, "noble", "universe", "foo", "1.1")
+            .source(RepoKind::Staging, "noble", "main", "bar", "2.0")
+            .source(RepoKind::Staging, "noble", "universe", "bar", "2.0")
+            .fetch(&[])
+            .await;
+        let staging = version(&fetched, "foo", Codename::Noble, RepoKind::Staging);
+        assert_eq!(staging.version, "1.1");
+        assert_eq!(
+            errors(staging),
+            [(
+                Severity::Conflict,
+                "Conflicting versions in noble: 1.0 in main, 1.1 in universe".to_string()
+            )]
+        );
+        // The same version in both is not a conflict
+        assert!(errors(version(&fetched, "bar", Codename::Noble, RepoKind::Staging)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_release_is_a_repo_error() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.0")
+            .release_field(
+                RepoKind::Release,
+                "noble",
+                "Valid-Until",
+                "Sat, 01 Jan 2000 00:00:00 UTC",
+            )
+            .source(RepoKind::Staging, "noble", "main", "foo", "1.0")
+            .release_field(
+                RepoKind::Staging,
+                "noble",
+                "Valid-Until",
+                "Fri, 01 Jan 2100 00:00:00 UTC",
+            )
+            .fetch(&[])
+            .await;
+        assert_eq!(
+            fetched.repo_errors,
+            [(
+                RepoKind::Release,
+                "noble: Release expired, valid until Sat, 01 Jan 2000 00:00:00 UTC".to_string()
+            )]
+        );
+        assert_eq!(fetched.expired[&RepoKind::Release], ["noble"]);
+        assert!(!fetched.expired.contains_key(&RepoKind::Staging));
+        let counts = ErrorCounts::new(&args(&[]), &fetched);
+        assert_eq!(counts.repo_errors, 1);
+        assert_eq!(counts.repos[&RepoKind::Release], 1);
+        assert_eq!(counts.total, 1);
+    }
+
+    #[tokio::test]
+    async fn expected_package_provided_by_a_binary_is_not_missing() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.0")
+            .binary(
+                RepoKind::Release,
+                "noble",
+                "main",
+                "Package: foo-bin\nSource: foo\nVersion: 1.0\nArchitecture: amd64\nProvides: virtual-foo (= 1.0)\n",
+            )
+            .set(r#"expected_packages.release.noble=["virtual-foo", "virtual-bar"]"#)
+            .fetch(&["--with-binaries"])
+            .await;
+        assert!(
+            !fetched
+                .apt_infos
+                .contains_key(&("virtual-foo".to_string(), Codename::Noble))
+        );
+        assert!(
+            version(&fetched, "foo", Codename::Noble, RepoKind::Release)
+                .provides
+                .contains("virtual-foo")
+        );
+        // Nothing provides this one, so it is still missing
+        assert_eq!(
+            fetched.apt_infos[&("virtual-bar".to_string(), Codename::Noble)].missing,
+            [RepoKind::Release]
+        );
+    }
+
+    #[tokio::test]
+    async fn extra_suite_is_compared_without_replacing_the_standard_suite() {
+        let fetched = Fixture::default()
+            .source(RepoKind::Release, "noble", "main", "foo", "1.1")
+            .source(RepoKind::Release, "noble", "main", "bar", "2.0")
+            .source(RepoKind::Release, "noble-proposed", "main", "foo", "1.0")
+            .source(RepoKind::Release, "noble-proposed", "main", "bar", "2.1")
+            .source(RepoKind::Release, "noble-proposed", "main", "baz", "3.0")
+            .set(r#"repos.release.extra_suites=["-proposed"]"#)
+            .fetch(&[])
+            .await;
+        let foo = version(&fetched, "foo", Codename::Noble, RepoKind::Release);
+        assert_eq!((foo.version.as_str(), foo.suite.as_str()), ("1.1", "noble"));
+        assert_eq!(
+            errors(foo),
+            [
+                (Severity::Warning, "Not in Staging".to_string()),
+                (Severity::Downgrade, "Older in noble-proposed".to_string()),
+            ]
+        );
+        let bar = version(&fetched, "bar", Codename::Noble, RepoKind::Release);
+        assert_eq!(bar.version, "2.0");
+        assert_eq!(
+            errors(bar),
+            [(Severity::Warning, "Not in Staging".to_string())]
+        );
+        let proposed: Vec<_> = fetched.apt_infos[&("bar".to_string(), Codename::Noble)]
+            .extra_suites
+            .iter()
+            .map(|extra| (extra.suite.as_str(), extra.version.as_str()))
+            .collect();
+        assert_eq!(proposed, [("noble-proposed", "2.1")]);
+        // Only in the extra suite, so no version users get to compare against
+        assert!(
+            !fetched
+                .apt_infos
+                .contains_key(&("baz".to_string(), Codename::Noble))
+        );
+    }
 }
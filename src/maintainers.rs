@@ -0,0 +1,111 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{Args, config::*, filtered_apt_infos, table::print_table};
+
+#[derive(Serialize)]
+struct Row {
+    // Name used by most of the packages, as teams are often named differently per package
+    name: String,
+    email: Option<String>,
+    team: bool,
+    packages: usize,
+    with_errors: usize,
+}
+
+// Splits a Maintainer field like "Name <email>" into the name and lowercased email
+fn parse_maintainer(maintainer: &str) -> (&str, Option<String>) {
+    match maintainer.rsplit_once('<') {
+        Some((name, email)) => (
+            name.trim(),
+            Some(email.trim_end_matches('>').trim().to_lowercase()),
+        ),
+        None => (maintainer.trim(), None),
+    }
+}
+
+// Mailing lists and role addresses shared by a team, like ubuntu-devel-discuss@lists.ubuntu.com
+// or pkg-gnome-maintainers@lists.alioth.debian.org, as opposed to one person
+fn is_team(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    domain.starts_with("lists.")
+        || ["maintainers", "team", "devel", "packagers", "discuss"]
+            .iter()
+            .any(|word| local.contains(word))
+}
+
+// Prints packages and packages with errors by maintainer, so it is clear who owns the most
+// problems. Maintainers are grouped by email, which a team keeps even when its name varies.
+pub async fn maintainers(args: &Args, config: &Config, json: bool) -> Result<()> {
+    let apt_infos = filtered_apt_infos(args, config).await?.apt_infos;
+    // Packages are counted once across codenames, with errors if they have any in one
+    let mut packages = BTreeMap::<String, BTreeMap<&str, bool>>::new();
+    let mut names = BTreeMap::<String, BTreeMap<&str, usize>>::new();
+    for ((package, _codename), apt_info) in apt_infos.iter() {
+        let (name, email) = match apt_info.maintainer() {
+            Some(maintainer) => parse_maintainer(maintainer),
+            None => ("Unknown", None),
+        };
+        let key = email.unwrap_or_else(|| name.to_lowercase());
+        *names
+            .entry(key.clone())
+            .or_default()
+            .entry(name)
+            .or_default() += 1;
+        *packages
+            .entry(key)
+            .or_default()
+            .entry(package.as_str())
+            .or_default() |= apt_info.error_count() > 0;
+    }
+
+    let mut rows = Vec::new();
+    for (key, packages) in packages {
+        let name = names[&key]
+            .iter()
+            .max_by_key(|(_name, count)| **count)
+            .map_or("", |(name, _count)| name)
+            .to_string();
+        let email = key.contains('@').then_some(key);
+        rows.push(Row {
+            name,
+            team: email.as_deref().is_some_and(is_team),
+            email,
+            packages: packages.len(),
+            with_errors: packages.values().filter(|errors| **errors).count(),
+        });
+    }
+    rows.sort_by(|a, b| {
+        b.with_errors
+            .cmp(&a.with_errors)
+            .then(b.packages.cmp(&a.packages))
+            .then(a.name.cmp(&b.name))
+    });
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    let mut table = vec![vec![
+        "Maintainer".to_string(),
+        "Email".to_string(),
+        "Team".to_string(),
+        "Packages".to_string(),
+        "With errors".to_string(),
+    ]];
+    for row in rows.iter() {
+        table.push(vec![
+            row.name.clone(),
+            row.email.clone().unwrap_or_default(),
+            if row.team { "yes" } else { "" }.to_string(),
+            row.packages.to_string(),
+            row.with_errors.to_string(),
+        ]);
+    }
+    print_table(&table);
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io::Write, path::Path};
+use url::Url;
+
+use crate::{AptInfo, FailedFetches, Fetched, RepoErrors, RepoWarnings, apt, config::*};
+
+// Bumped when the saved model changes in a way older files cannot be read as
+const MODEL_VERSION: u32 = 1;
+
+#[derive(Deserialize, Serialize)]
+struct Model {
+    version: u32,
+    // Packages by name and codename, as a list since JSON keys must be strings
+    packages: Vec<(String, Codename, AptInfo)>,
+    repo_errors: RepoErrors,
+    #[serde(default)]
+    repo_warnings: RepoWarnings,
+    #[serde(default)]
+    failed_fetches: FailedFetches,
+    #[serde(default)]
+    updated: BTreeMap<RepoKind, i64>,
+    #[serde(default)]
+    expired: BTreeMap<RepoKind, Vec<String>>,
+}
+
+// Saves packages with their computed errors, so they can be rendered without fetching
+pub fn save(path: &Path, fetched: &Fetched) -> Result<()> {
+    let model = Model {
+        version: MODEL_VERSION,
+        packages: fetched
+            .apt_infos
+            .iter()
+            .map(|((package, codename), apt_info)| (package.clone(), *codename, apt_info.clone()))
+            .collect(),
+        repo_errors: fetched.repo_errors.clone(),
+        repo_warnings: fetched.repo_warnings.clone(),
+        failed_fetches: fetched.failed_fetches.clone(),
+        updated: fetched.updated.clone(),
+        expired: fetched.expired.clone(),
+    };
+    let mut file =
+        fs::File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    serde_json::to_writer(&mut file, &model)?;
+    writeln!(file)?;
+    log::info!("saved model to {}", path.display());
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Fetched> {
+    let data =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&data, &path.display().to_string())
+}
+
+// Loads a model from an http or https URL, like one published by another instance, or else
+// from a file
+pub async fn load_location(config: &Config, location: &str) -> Result<Fetched> {
+    let url = match Url::parse(location) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return load(Path::new(location)),
+    };
+    if !apt::host_allowed(&config.allowed_hosts, url.host_str()) {
+        return Err(anyhow!("{url}: host not in allowed_hosts"));
+    }
+    let data = apt::client_for(config, &url)?
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to fetch {url}"))?
+        .text()
+        .await
+        .with_context(|| format!("failed to fetch {url}"))?;
+    parse(&data, url.as_str())
+}
+
+fn parse(data: &str, source: &str) -> Result<Fetched> {
+    let model: Model =
+        serde_json::from_str(data).with_context(|| format!("failed to parse model {source}"))?;
+    if model.version != MODEL_VERSION {
+        return Err(anyhow!(
+            "{source}: model version {} is not supported, expected {MODEL_VERSION}",
+            model.version
+        ));
+    }
+    let apt_infos = model
+        .packages
+        .into_iter()
+        .map(|(package, codename, apt_info)| ((package, codename), apt_info))
+        .collect();
+    Ok(Fetched {
+        apt_infos,
+        repo_errors: model.repo_errors,
+        repo_warnings: model.repo_warnings,
+        failed_fetches: model.failed_fetches,
+        updated: model.updated,
+        expired: model.expired,
+    })
+}
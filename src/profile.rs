@@ -0,0 +1,22 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::BufWriter, path::Path};
+use tracing_flame::{FlameLayer, FlushGuard};
+use tracing_subscriber::{prelude::*, registry::Registry};
+
+// Records the time spent in each span to path as folded stacks, which inferno-flamegraph or
+// flamegraph.pl turn into a flamegraph. Spans are only timed while their future is being polled,
+// so time waiting on the network or a fetch permit is left out. Samples from the tokio worker
+// threads are merged, as which thread polled a task is not interesting, and time outside any span
+// is dropped. Without this, no subscriber is installed and the spans cost a check of a static
+// each.
+pub fn start(path: &Path) -> Result<FlushGuard<BufWriter<File>>> {
+    let (layer, guard) = FlameLayer::with_file(path)
+        .with_context(|| format!("failed to create profile {}", path.display()))?;
+    let layer = layer
+        .with_threads_collapsed(true)
+        .with_empty_samples(false)
+        .with_file_and_line(false);
+    tracing::subscriber::set_global_default(Registry::default().with(layer))
+        .context("failed to install profiling subscriber")?;
+    Ok(guard)
+}
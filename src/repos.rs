@@ -0,0 +1,69 @@
+use crate::{apt, config::*};
+
+// Prints every repo as it will be fetched, after merging defaults and config files, to find out
+// why a repo or suite was or was not fetched without making any requests
+pub fn list_repos(config: &Config) {
+    for (i, repo_kind) in RepoKind::all().into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let url = config.repo_url(repo_kind);
+        println!("{} ({})", repo_kind.as_str(), repo_kind.id());
+        println!("  URL: {url}");
+        if !apt::host_allowed(&config.allowed_hosts, url.host_str()) {
+            println!("  Host not in allowed_hosts, nothing will be fetched");
+        }
+        if config.insecure_host(url.host_str()) {
+            println!("  TLS certificates not checked, host is in http.insecure_hosts");
+        }
+        let newer_than: Vec<_> = repo_kind
+            .must_be_newer_than()
+            .iter()
+            .map(|kind| kind.as_str().to_string())
+            .collect();
+        if !newer_than.is_empty() {
+            println!("  Must be newer than: {}", newer_than.join(", "));
+        }
+        let aliases = config.suite_aliases(repo_kind);
+        if !aliases.is_empty() {
+            println!("  Suite aliases: {}", aliases.join(", "));
+        }
+        if let Some(headers) = config
+            .headers(repo_kind)
+            .filter(|headers| !headers.is_empty())
+        {
+            // Only names, values may be credentials
+            let names: Vec<_> = headers.keys().map(String::as_str).collect();
+            println!("  Headers: {}", names.join(", "));
+        }
+        println!(
+            "  Version links: {}",
+            config.version_link(repo_kind).as_str()
+        );
+        if let Some(max_age_hours) = config.max_age_hours(repo_kind) {
+            println!("  Max age: {max_age_hours} hours");
+        }
+        for codename in repo_kind.codenames() {
+            let suites: Vec<_> = config
+                .suites(repo_kind, *codename)
+                .iter()
+                .map(|suite| suite.to_string())
+                .collect();
+            let archs: Vec<_> = config
+                .allowed_archs(repo_kind, *codename)
+                .iter()
+                .map(|arch| arch.as_str())
+                .collect();
+            println!(
+                "  {}: suites {}; archs {}",
+                codename.as_str(),
+                suites.join(", "),
+                archs.join(", ")
+            );
+            let components = config.expected_components(repo_kind, *codename);
+            if !components.is_empty() {
+                println!("    Required components: {}", components.join(", "));
+            }
+        }
+    }
+}
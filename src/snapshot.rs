@@ -0,0 +1,24 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::{AptInfos, config::*};
+
+// Writes one "package codename repo version" line per version found, sorted by package, codename,
+// and repo, with nothing that changes between runs, so successive snapshots diff cleanly
+pub fn snapshot_report<W: Write>(snapshot: &mut W, apt_infos: &AptInfos) -> Result<()> {
+    for ((package, codename), apt_info) in apt_infos.iter() {
+        for repo_kind in RepoKind::all() {
+            let Some(version) = apt_info.version(repo_kind) else {
+                continue;
+            };
+            writeln!(
+                snapshot,
+                "{package} {} {} {}",
+                codename.as_str(),
+                repo_kind.id(),
+                version.version
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use anyhow::{Result, anyhow};
+use tokio::net::{UdpSocket, lookup_host};
+
+use crate::{ErrorCounts, apt::host_allowed, config::*};
+
+// Gauges for the error counts, one line each as in "poparazzi.errors:35|g"
+fn gauges(prefix: &str, counts: &ErrorCounts) -> Vec<String> {
+    let mut gauges = vec![
+        format!("{prefix}.errors:{}|g", counts.total),
+        format!("{prefix}.warnings:{}|g", counts.warnings),
+    ];
+    for (repo_kind, errors) in counts.repos.iter() {
+        gauges.push(format!(
+            "{prefix}.errors.repo.{}:{errors}|g",
+            repo_kind.id()
+        ));
+    }
+    for (codename, errors) in counts.codenames.iter() {
+        gauges.push(format!(
+            "{prefix}.errors.codename.{}:{errors}|g",
+            codename.as_str()
+        ));
+    }
+    gauges
+}
+
+// Sends each gauge in its own datagram, which every StatsD server accepts
+async fn send_gauges(config: &Config, address: &str, gauges: &[String]) -> Result<()> {
+    if !host_allowed(&config.allowed_hosts, config.statsd_host()) {
+        return Err(anyhow!("host not in allowed_hosts"));
+    }
+    let addr = lookup_host(address)
+        .await?
+        .next()
+        .ok_or(anyhow!("no addresses found"))?;
+    let socket = UdpSocket::bind(if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    })
+    .await?;
+    for gauge in gauges {
+        socket.send_to(gauge.as_bytes(), addr).await?;
+    }
+    Ok(())
+}
+
+// Pushes the error counts of a run to statsd_address if it is set. UDP gives no delivery
+// guarantees anyway, so failures are logged without failing the run.
+pub async fn send(config: &Config, counts: &ErrorCounts) {
+    let Some(address) = &config.statsd_address else {
+        return;
+    };
+    let gauges = gauges(&config.statsd_prefix, counts);
+    match send_gauges(config, address, &gauges).await {
+        Ok(()) => log::info!("sent {} gauges to StatsD at {address}", gauges.len()),
+        Err(err) => log::warn!("failed to send metrics to StatsD at {address}: {err:#}"),
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::{apt::AptRepo, config::*, repo_age, table::print_table};
+
+// Prints the codename, date, and components of every Release file, without fetching Sources
+pub async fn status(config: &Config) -> Result<()> {
+    let mut tasks = Vec::new();
+    for repo_kind in RepoKind::all() {
+        let repo = AptRepo::new(config, repo_kind)?;
+        for codename in repo_kind.codenames() {
+            for suite in config.suites(repo_kind, *codename) {
+                let suite = suite.to_string();
+                tasks.push((repo_kind, suite.clone(), {
+                    let repo = repo.clone();
+                    tokio::spawn(async move { repo.release(&suite).await })
+                }));
+            }
+        }
+    }
+
+    let mut rows = vec![vec![
+        "Repo".to_string(),
+        "Suite".to_string(),
+        "Codename".to_string(),
+        "Date".to_string(),
+        "Components".to_string(),
+        "Status".to_string(),
+    ]];
+    let mut failed = 0;
+    let mut expired = 0;
+    let mut updated = BTreeMap::<RepoKind, i64>::new();
+    for (repo_kind, suite, task) in tasks {
+        let row = match task.await? {
+            Ok(releases) => {
+                let release = releases.into_iter().next().unwrap_or_default();
+                if let Some(timestamp) = release.timestamp() {
+                    let newest = updated.entry(repo_kind).or_insert(timestamp);
+                    *newest = (*newest).max(timestamp);
+                }
+                let status = if release.expired() {
+                    expired += 1;
+                    format!(
+                        "Expired, valid until {}",
+                        release.valid_until.as_deref().unwrap_or_default()
+                    )
+                } else {
+                    "OK".to_string()
+                };
+                vec![
+                    repo_kind.as_str().to_string(),
+                    suite,
+                    release.codename.unwrap_or_default(),
+                    release.date.unwrap_or_default(),
+                    release.components.unwrap_or_default().join(" "),
+                    status,
+                ]
+            }
+            Err(err) => {
+                failed += 1;
+                vec![
+                    repo_kind.as_str().to_string(),
+                    suite,
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    format!("{err:#}"),
+                ]
+            }
+        };
+        rows.push(row);
+    }
+    print_table(&rows);
+
+    // Repos with a max_age_hours pass if their newest Release is recent enough
+    for repo_kind in RepoKind::all() {
+        if let Some((hours, max_age_hours)) = repo_age(config, &updated, repo_kind) {
+            let result = if hours > max_age_hours as i64 {
+                "FAIL"
+            } else {
+                "PASS"
+            };
+            println!(
+                "{}: updated {hours} hours ago, max_age_hours {max_age_hours}: {result}",
+                repo_kind.as_str()
+            );
+        }
+    }
+
+    if failed > 0 {
+        log::warn!("{failed} Release files could not be fetched");
+    }
+    if expired > 0 {
+        log::warn!("{expired} Release files are past their Valid-Until");
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+// Prints rows as columns aligned to the widest cell, the first row being the header
+pub fn print_table(rows: &[Vec<String>]) {
+    let mut widths = Vec::new();
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate() {
+            if i >= widths.len() {
+                widths.push(0);
+            }
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:width$}  ", cell, width = widths[i]));
+            }
+        }
+        println!("{}", line.trim_end());
+    }
+}
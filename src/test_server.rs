@@ -0,0 +1,119 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Clone)]
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Routes = BTreeMap<String, VecDeque<Response>>;
+
+// A plain HTTP server on localhost for tests, answering each path with canned responses and
+// recording every request as in "GET /dists/noble/Release"
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    // Each path answers with its responses in turn and then keeps repeating the last one, any
+    // other path gets a 404
+    pub async fn start(routes: Vec<(&str, Vec<Response>)>) -> Self {
+        let routes: Routes = routes
+            .into_iter()
+            .map(|(path, responses)| (path.to_string(), responses.into()))
+            .collect();
+        let routes = Arc::new(Mutex::new(routes));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve(stream, routes.clone(), requests.clone()));
+                }
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+// Answers a single request and closes the connection, so keep-alive never comes into play
+async fn serve(
+    mut stream: TcpStream,
+    routes: Arc<Mutex<Routes>>,
+    requests: Arc<Mutex<Vec<String>>>,
+) {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(len) => head.extend_from_slice(&buf[..len]),
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    requests.lock().unwrap().push(format!("{method} {path}"));
+    let response = {
+        let mut routes = routes.lock().unwrap();
+        match routes.get_mut(&path) {
+            Some(responses) if responses.len() > 1 => responses.pop_front().unwrap(),
+            Some(responses) => responses.front().cloned().unwrap(),
+            None => Response::status(404),
+        }
+    };
+    let mut data = format!(
+        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        data.push_str(&format!("{name}: {value}\r\n"));
+    }
+    data.push_str("\r\n");
+    let mut data = data.into_bytes();
+    if method != "HEAD" {
+        data.extend_from_slice(&response.body);
+    }
+    let _ = stream.write_all(&data).await;
+    let _ = stream.shutdown().await;
+}
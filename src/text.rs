@@ -0,0 +1,101 @@
+use anyhow::Result;
+use std::io::Write;
+
+use crate::{AptInfos, FailedFetches, RepoErrors, RepoWarnings, config::*};
+
+// Width to wrap lines at, so the report reads well in a terminal or email client
+const WRAP_WIDTH: usize = 78;
+
+// Writes words indented by prefix, wrapping at WRAP_WIDTH with a deeper indent on continuation
+// lines. The first line always has the package and codename, so grep finds every entry.
+fn write_wrapped<W: Write>(text: &mut W, prefix: &str, line: &str) -> Result<()> {
+    let mut current = prefix.to_string();
+    for word in line.split(' ') {
+        if current.len() > prefix.len() && current.len() + 1 + word.len() > WRAP_WIDTH {
+            writeln!(text, "{current}")?;
+            current = format!("{prefix}    ");
+        } else if current.len() > prefix.len() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    writeln!(text, "{current}")?;
+    Ok(())
+}
+
+// Writes a plain text summary of errors, grouped by the repo they were found in
+pub fn text_report<W: Write>(
+    text: &mut W,
+    config: &Config,
+    apt_infos: &AptInfos,
+    repo_errors: &RepoErrors,
+    repo_warnings: &RepoWarnings,
+    failed_fetches: &FailedFetches,
+) -> Result<()> {
+    let mut total_errors = repo_errors.len();
+    for (_, apt_info) in apt_infos.iter() {
+        total_errors += apt_info.error_count();
+    }
+    writeln!(text, "{}: {total_errors} errors", config.report_title())?;
+
+    if !failed_fetches.is_empty() {
+        writeln!(text)?;
+        writeln!(text, "Failed fetches ({})", failed_fetches.len())?;
+        for failed_fetch in failed_fetches.iter() {
+            write_wrapped(
+                text,
+                "  ",
+                &format!("{}: {}", failed_fetch.location(), failed_fetch.error),
+            )?;
+        }
+    }
+
+    if !repo_errors.is_empty() {
+        writeln!(text)?;
+        writeln!(text, "Repos ({} errors)", repo_errors.len())?;
+        for (repo_kind, message) in repo_errors.iter() {
+            write_wrapped(text, "  ", &format!("{}: {message}", repo_kind.as_str()))?;
+        }
+    }
+
+    if !repo_warnings.is_empty() {
+        writeln!(text)?;
+        writeln!(text, "Repos ({} warnings)", repo_warnings.len())?;
+        for (repo_kind, message) in repo_warnings.iter() {
+            write_wrapped(text, "  ", &format!("{}: {message}", repo_kind.as_str()))?;
+        }
+    }
+
+    for repo_kind in RepoKind::all() {
+        let mut lines = Vec::new();
+        for ((package, codename), apt_info) in apt_infos.iter() {
+            if apt_info.missing.contains(&repo_kind) {
+                lines.push(format!(
+                    "{package} {codename}: expected but missing",
+                    codename = codename.as_str()
+                ));
+            }
+            let Some(version) = apt_info.version(repo_kind) else {
+                continue;
+            };
+            for error in version.errors.borrow().iter() {
+                lines.push(format!(
+                    "{package} {codename} {}: [{}] {}",
+                    version.version,
+                    error.severity.as_str(),
+                    error.message,
+                    codename = codename.as_str()
+                ));
+            }
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        writeln!(text)?;
+        writeln!(text, "{} ({} errors)", repo_kind.as_str(), lines.len())?;
+        for line in lines {
+            write_wrapped(text, "  ", &line)?;
+        }
+    }
+    Ok(())
+}
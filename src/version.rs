@@ -0,0 +1,29 @@
+use std::cmp::Ordering;
+
+// Distro suffixes stripped from the upstream version in upstream-only mode, with everything
+// after them
+const DISTRO_SUFFIXES: &[&str] = &["~ubuntu", "+ubuntu", "~pop", "+pop"];
+
+// Returns the upstream part of a version, keeping the epoch but removing the Debian revision
+// after the last - and then any distro suffix, so 1:2.0~pop1-0ubuntu3 becomes 1:2.0
+pub fn upstream_version(version: &str) -> &str {
+    let mut upstream = match version.rfind('-') {
+        Some(index) => &version[..index],
+        None => version,
+    };
+    for suffix in DISTRO_SUFFIXES {
+        if let Some(index) = upstream.find(suffix) {
+            upstream = &upstream[..index];
+        }
+    }
+    upstream
+}
+
+// Compares versions strictly, or only by their upstream part when upstream_only is set
+pub fn compare_versions(upstream_only: bool, a: &str, b: &str) -> Ordering {
+    if upstream_only {
+        deb_version::compare_versions(upstream_version(a), upstream_version(b))
+    } else {
+        deb_version::compare_versions(a, b)
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+use crate::{Args, config::*, load_apt_infos};
+
+// Prints every version of a package across codenames and repos, lowest first, with the places
+// sharing a version on one line, compact enough to paste into chat
+pub async fn versions(args: &Args, config: &Config, package: &str) -> Result<()> {
+    let apt_infos = load_apt_infos(args, config).await?.apt_infos;
+    let mut places = BTreeMap::<&str, Vec<String>>::new();
+    for ((name, codename), apt_info) in apt_infos.iter() {
+        if name != package {
+            continue;
+        }
+        for repo_kind in RepoKind::all() {
+            let Some(version) = apt_info.version(repo_kind) else {
+                continue;
+            };
+            let mut place = format!("{}/{}", codename.as_str(), repo_kind.as_str());
+            let errors = version.errors.borrow();
+            if !errors.is_empty() {
+                let errors: Vec<String> = errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.severity.as_str(), error.message))
+                    .collect();
+                place.push_str(&format!(" ({})", errors.join("; ")));
+            }
+            places.entry(&version.version).or_default().push(place);
+        }
+    }
+    if places.is_empty() {
+        println!("{package} was not found in any Pop repo");
+        return Ok(());
+    }
+
+    let mut places: Vec<_> = places.into_iter().collect();
+    places.sort_by(|(a, _), (b, _)| deb_version::compare_versions(a, b));
+    let width = places
+        .iter()
+        .map(|(version, _)| version.len())
+        .max()
+        .unwrap_or(0);
+    println!("{package}");
+    for (version, places) in places {
+        println!("  {version:width$}  {}", places.join(", "));
+    }
+    Ok(())
+}